@@ -0,0 +1,12 @@
+-- Busiest pickup zones of $borough (needs the taxi zone lookup)
+SELECT
+    z.zone AS pickup_zone,
+    COUNT(*) AS trip_count,
+    AVG(t.fare_amount) AS avg_fare_amount
+FROM yellow t
+JOIN zones z ON t.pu_location_id = z.location_id
+WHERE z.borough = $borough
+  AND date_part('year', t.pickup_datetime) = $year
+GROUP BY 1
+ORDER BY trip_count DESC
+LIMIT 10;
@@ -0,0 +1,10 @@
+-- Daily trip counts from $start_date until the end of $year
+SELECT
+    date_trunc('day', pickup_datetime) AS pickup_day,
+    COUNT(*) AS trip_count,
+    AVG(total_amount) AS avg_total_amount
+FROM yellow
+WHERE pickup_datetime >= $start_date
+  AND date_part('year', pickup_datetime) = $year
+GROUP BY 1
+ORDER BY 1;
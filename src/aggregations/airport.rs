@@ -0,0 +1,206 @@
+use super::duration::MULTI_DAY_MINUTES;
+use super::{Aggregation, AggregationInput};
+use anyhow::Result;
+use datafusion::arrow::datatypes::{DataType, TimeUnit};
+use datafusion::functions::datetime::expr_fn::date_trunc;
+use datafusion::functions_aggregate::expr_fn::{avg, count, sum};
+use datafusion::logical_expr::SortExpr;
+use datafusion::prelude::*;
+
+/// Airport taxi zones (`LocationID`)
+const AIRPORTS: [(i64, &str); 3] = [(132, "JFK"), (138, "LGA"), (1, "EWR")];
+
+/// `RatecodeID` of the JFK flat fare and of Newark trips
+const RATECODE_JFK: i64 = 2;
+const RATECODE_NEWARK: i64 = 3;
+
+/// JFK <-> Manhattan flat fare went from $52 to $70 on this date
+const JFK_FLAT_FARE_CHANGE: &str = "2022-12-19 00:00:00";
+const JFK_FLAT_FARE_BEFORE: f64 = 52.0;
+const JFK_FLAT_FARE_AFTER: f64 = 70.0;
+
+/// Airport trips by airport, direction and pickup month.
+///
+/// A trip with the JFK (2) or Newark (3) `RatecodeID` belongs to that airport, whatever
+/// its zones; other trips belong to the airport zone they start or end in. The direction
+/// is relative to that airport. `flat_fare_compliance` is the share of JFK flat-rate trips
+/// whose fare equals the flat fare in effect.
+pub struct AirportTrips;
+
+/// `CASE location WHEN 132 THEN 'JFK' ... END`
+fn airport_of(location: &str) -> Result<Expr> {
+    let (first_id, first_name) = AIRPORTS[0];
+    let mut case = when(col(location).eq(lit(first_id)), lit(first_name));
+    for (id, name) in &AIRPORTS[1..] {
+        case.when(col(location).eq(lit(*id)), lit(*name));
+    }
+    Ok(case.end()?)
+}
+
+fn airport_of_sql(location: &str) -> String {
+    let branches: Vec<String> = AIRPORTS
+        .iter()
+        .map(|(id, name)| format!("WHEN {} THEN '{}'", id, name))
+        .collect();
+    format!("CASE {} {} END", location, branches.join(" "))
+}
+
+impl Aggregation for AirportTrips {
+    fn name(&self) -> &'static str {
+        "airport_trips"
+    }
+
+    fn description(&self) -> &'static str {
+        "JFK, LGA and EWR trips, flat-fare compliance, totals, tips and duration by month"
+    }
+
+    fn dataframe(&self, input: &AggregationInput) -> Result<DataFrame> {
+        let by_rate = when(col("ratecode_id").eq(lit(RATECODE_JFK)), lit("JFK"))
+            .when(col("ratecode_id").eq(lit(RATECODE_NEWARK)), lit("EWR"))
+            .end()?;
+        // The rate code wins when it disagrees with the zones
+        let airport = coalesce(vec![
+            by_rate,
+            airport_of("pu_location_id")?,
+            airport_of("do_location_id")?,
+        ]);
+        let direction = when(
+            airport_of("pu_location_id")?.eq(col("airport")),
+            lit("from_airport"),
+        )
+        .when(
+            airport_of("do_location_id")?.eq(col("airport")),
+            lit("to_airport"),
+        )
+        .otherwise(lit("rate_code_only"))?;
+
+        let flat_fare = when(
+            col("pickup_datetime").lt(cast(
+                lit(JFK_FLAT_FARE_CHANGE),
+                DataType::Timestamp(TimeUnit::Nanosecond, None),
+            )),
+            lit(JFK_FLAT_FARE_BEFORE),
+        )
+        .otherwise(lit(JFK_FLAT_FARE_AFTER))?;
+        let is_flat = col("airport")
+            .eq(lit("JFK"))
+            .and(col("ratecode_id").eq(lit(RATECODE_JFK)));
+        let flag = |predicate: Expr| -> Result<Expr> {
+            Ok(sum(when(predicate, lit(1i64)).otherwise(lit(0i64))?))
+        };
+        let valid_duration = col("trip_duration_minutes")
+            .gt(lit(0.0))
+            .and(col("trip_duration_minutes").lt(lit(MULTI_DAY_MINUTES)));
+
+        let base = input
+            .trips
+            .clone()
+            .with_column("airport", airport)?
+            .with_column("direction", direction)?
+            .filter(col("airport").is_not_null())?
+            .aggregate(
+                vec![
+                    col("airport"),
+                    col("direction"),
+                    input
+                        .window
+                        .bucket(date_trunc(lit("month"), col("pickup_datetime")))?
+                        .alias("pickup_month"),
+                ],
+                vec![
+                    count(lit(1)).alias("trip_count"),
+                    flag(col("airport_fee").gt(lit(0.0)))?.alias("airport_fee_trips"),
+                    flag(is_flat.clone())?.alias("flat_fare_trips"),
+                    flag(is_flat.and(col("fare_amount").eq(flat_fare)))?
+                        .alias("flat_fare_standard_trips"),
+                    avg(col("total_amount")).alias("avg_total_amount"),
+                    avg(col("tip_amount")).alias("avg_tip_amount"),
+                    sum(col("tip_amount")).alias("sum_tip_amount"),
+                    sum(col("total_amount")).alias("sum_total_amount"),
+                    avg(when(valid_duration, col("trip_duration_minutes")).end()?)
+                        .alias("avg_duration_minutes"),
+                ],
+            )?;
+
+        Ok(base.select(vec![
+            col("airport"),
+            col("direction"),
+            col("pickup_month"),
+            col("trip_count"),
+            col("airport_fee_trips"),
+            col("flat_fare_trips"),
+            (cast(col("flat_fare_standard_trips"), DataType::Float64)
+                / nullif(cast(col("flat_fare_trips"), DataType::Float64), lit(0.0)))
+            .alias("flat_fare_compliance"),
+            col("avg_total_amount"),
+            col("avg_tip_amount"),
+            (col("sum_tip_amount") / col("sum_total_amount")).alias("tip_rate"),
+            col("avg_duration_minutes"),
+        ])?)
+    }
+
+    fn sql(&self, input: &AggregationInput) -> String {
+        format!(
+            r#"
+            SELECT
+                airport,
+                CASE
+                    WHEN pu_airport = airport THEN 'from_airport'
+                    WHEN do_airport = airport THEN 'to_airport'
+                    ELSE 'rate_code_only'
+                END AS direction,
+                {pickup_month} AS pickup_month,
+                COUNT(*) AS trip_count,
+                SUM(CASE WHEN airport_fee > 0 THEN 1 ELSE 0 END) AS airport_fee_trips,
+                SUM(CASE WHEN {is_flat} THEN 1 ELSE 0 END) AS flat_fare_trips,
+                CAST(SUM(CASE WHEN {is_flat} AND fare_amount = flat_fare THEN 1 ELSE 0 END) AS DOUBLE)
+                    / NULLIF(CAST(SUM(CASE WHEN {is_flat} THEN 1 ELSE 0 END) AS DOUBLE), 0.0)
+                    AS flat_fare_compliance,
+                AVG(total_amount) AS avg_total_amount,
+                AVG(tip_amount) AS avg_tip_amount,
+                SUM(tip_amount) / SUM(total_amount) AS tip_rate,
+                AVG(CASE WHEN trip_duration_minutes > 0 AND trip_duration_minutes < {multi_day:.1}
+                    THEN trip_duration_minutes END) AS avg_duration_minutes
+            FROM (
+                SELECT
+                    *,
+                    {pu_airport} AS pu_airport,
+                    {do_airport} AS do_airport,
+                    COALESCE(
+                        CASE ratecode_id WHEN {jfk} THEN 'JFK' WHEN {newark} THEN 'EWR' END,
+                        {pu_airport},
+                        {do_airport}
+                    ) AS airport,
+                    CASE WHEN pickup_datetime < TIMESTAMP '{change}' THEN {before:.1} ELSE {after:.1} END
+                        AS flat_fare
+                FROM {table}
+                {where_clause}
+            ) t
+            WHERE airport IS NOT NULL
+            GROUP BY 1, 2, 3
+        "#,
+            pickup_month = input
+                .window
+                .bucket_sql("date_trunc('month', pickup_datetime)"),
+            jfk = RATECODE_JFK,
+            is_flat = format!("airport = 'JFK' AND ratecode_id = {}", RATECODE_JFK),
+            newark = RATECODE_NEWARK,
+            multi_day = MULTI_DAY_MINUTES,
+            pu_airport = airport_of_sql("pu_location_id"),
+            do_airport = airport_of_sql("do_location_id"),
+            change = JFK_FLAT_FARE_CHANGE,
+            before = JFK_FLAT_FARE_BEFORE,
+            after = JFK_FLAT_FARE_AFTER,
+            table = input.table,
+            where_clause = input.where_sql(),
+        )
+    }
+
+    fn sort_order(&self) -> Vec<SortExpr> {
+        vec![
+            col("airport").sort(true, true),
+            col("direction").sort(true, true),
+            col("pickup_month").sort(true, true),
+        ]
+    }
+}
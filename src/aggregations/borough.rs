@@ -0,0 +1,150 @@
+use super::{Aggregation, AggregationInput};
+use crate::zones::{zones_as, ZONES_TABLE};
+use anyhow::Result;
+use datafusion::functions_aggregate::expr_fn::{avg, count, sum};
+use datafusion::logical_expr::SortExpr;
+use datafusion::prelude::*;
+
+/// Trips and revenue by pickup borough/zone
+pub struct TripsByPickupZone;
+
+impl Aggregation for TripsByPickupZone {
+    fn name(&self) -> &'static str {
+        "trips_by_pickup_zone"
+    }
+
+    fn description(&self) -> &'static str {
+        "Trips and revenue by pickup borough/zone"
+    }
+
+    fn requires_zones(&self) -> bool {
+        true
+    }
+
+    fn dataframe(&self, input: &AggregationInput) -> Result<DataFrame> {
+        let zones = input.zones(self.name())?;
+
+        Ok(input
+            .trips
+            .clone()
+            .join(
+                zones_as(zones, "pickup_")?,
+                JoinType::Left,
+                &["pu_location_id"],
+                &["pickup_location_id"],
+                None,
+            )?
+            .aggregate(
+                vec![col("pickup_borough"), col("pickup_zone")],
+                vec![
+                    count(lit(1)).alias("trip_count"),
+                    sum(col("total_amount")).alias("total_revenue"),
+                    avg(col("fare_amount")).alias("avg_fare"),
+                ],
+            )?)
+    }
+
+    fn sql(&self, input: &AggregationInput) -> String {
+        format!(
+            r#"
+            SELECT
+                pz.borough AS pickup_borough,
+                pz.zone AS pickup_zone,
+                COUNT(*) AS trip_count,
+                SUM(t.total_amount) AS total_revenue,
+                AVG(t.fare_amount) AS avg_fare
+            FROM {table} t
+            LEFT JOIN {zones} pz ON t.pu_location_id = pz.location_id
+            {where_clause}
+            GROUP BY 1, 2
+        "#,
+            table = input.table,
+            zones = ZONES_TABLE,
+            where_clause = input.where_sql(),
+        )
+    }
+
+    fn sort_order(&self) -> Vec<SortExpr> {
+        vec![
+            col("trip_count").sort(false, true),
+            col("pickup_borough").sort(true, true),
+            col("pickup_zone").sort(true, true),
+        ]
+    }
+}
+
+/// Trips and revenue by origin-destination borough pair
+pub struct TripsByBoroughPair;
+
+impl Aggregation for TripsByBoroughPair {
+    fn name(&self) -> &'static str {
+        "trips_by_borough_pair"
+    }
+
+    fn description(&self) -> &'static str {
+        "Trips and revenue by origin-destination borough"
+    }
+
+    fn requires_zones(&self) -> bool {
+        true
+    }
+
+    fn dataframe(&self, input: &AggregationInput) -> Result<DataFrame> {
+        let zones = input.zones(self.name())?;
+
+        Ok(input
+            .trips
+            .clone()
+            .join(
+                zones_as(zones.clone(), "pickup_")?,
+                JoinType::Left,
+                &["pu_location_id"],
+                &["pickup_location_id"],
+                None,
+            )?
+            .join(
+                zones_as(zones, "dropoff_")?,
+                JoinType::Left,
+                &["do_location_id"],
+                &["dropoff_location_id"],
+                None,
+            )?
+            .aggregate(
+                vec![col("pickup_borough"), col("dropoff_borough")],
+                vec![
+                    count(lit(1)).alias("trip_count"),
+                    sum(col("total_amount")).alias("total_revenue"),
+                    avg(col("fare_amount")).alias("avg_fare"),
+                ],
+            )?)
+    }
+
+    fn sql(&self, input: &AggregationInput) -> String {
+        format!(
+            r#"
+            SELECT
+                pz.borough AS pickup_borough,
+                dz.borough AS dropoff_borough,
+                COUNT(*) AS trip_count,
+                SUM(t.total_amount) AS total_revenue,
+                AVG(t.fare_amount) AS avg_fare
+            FROM {table} t
+            LEFT JOIN {zones} pz ON t.pu_location_id = pz.location_id
+            LEFT JOIN {zones} dz ON t.do_location_id = dz.location_id
+            {where_clause}
+            GROUP BY 1, 2
+        "#,
+            table = input.table,
+            zones = ZONES_TABLE,
+            where_clause = input.where_sql(),
+        )
+    }
+
+    fn sort_order(&self) -> Vec<SortExpr> {
+        vec![
+            col("trip_count").sort(false, true),
+            col("pickup_borough").sort(true, true),
+            col("dropoff_borough").sort(true, true),
+        ]
+    }
+}
@@ -0,0 +1,157 @@
+use super::{Aggregation, AggregationInput};
+use crate::window::CALENDAR_TABLE;
+use anyhow::Result;
+use datafusion::common::ScalarValue;
+use datafusion::functions::datetime::expr_fn::{date_part, date_trunc};
+use datafusion::functions_aggregate::average::avg_udaf;
+use datafusion::functions_aggregate::expr_fn::{count, sum};
+use datafusion::functions_aggregate::sum::sum_udaf;
+use datafusion::logical_expr::expr::WindowFunction;
+use datafusion::logical_expr::{
+    AggregateUDF, ExprFunctionExt, SortExpr, WindowFrame, WindowFrameBound, WindowFrameUnits,
+};
+use datafusion::prelude::*;
+use std::sync::Arc;
+
+/// Rolling average widths in days
+const ROLLING_DAYS: [u64; 2] = [7, 28];
+
+/// Daily trips and revenue with rolling averages and year-to-date totals.
+///
+/// Every day of the time window gets a row (0 trips and 0 revenue on days without trips), so
+/// the rolling windows count days rather than rows. The first days of the window average
+/// over the days available so far.
+pub struct DailySeries;
+
+/// `aggregate(column) OVER (...)`, built further with [`ExprFunctionExt`]
+fn over(aggregate: Arc<AggregateUDF>, column: &str) -> Expr {
+    Expr::from(WindowFunction::new(aggregate, vec![col(column)]))
+}
+
+impl Aggregation for DailySeries {
+    fn name(&self) -> &'static str {
+        "daily_series"
+    }
+
+    fn description(&self) -> &'static str {
+        "Daily trips and revenue with 7/28-day rolling averages and year-to-date totals"
+    }
+
+    fn dataframe(&self, input: &AggregationInput) -> Result<DataFrame> {
+        let daily = input.trips.clone().aggregate(
+            vec![date_trunc(lit("day"), col("pickup_datetime")).alias("pickup_date")],
+            vec![
+                count(lit(1)).alias("day_trip_count"),
+                sum(col("total_amount")).alias("day_revenue"),
+            ],
+        )?;
+
+        // Step 1: gap-fill by joining onto the calendar (same rows as CALENDAR_TABLE)
+        let filled = input
+            .ctx
+            .read_batch(input.window.calendar()?)?
+            .join(daily, JoinType::Left, &["day"], &["pickup_date"], None)?
+            .select(vec![
+                col("day").alias("pickup_date"),
+                coalesce(vec![col("day_trip_count"), lit(0i64)]).alias("trip_count"),
+                coalesce(vec![col("day_revenue"), lit(0.0)]).alias("total_revenue"),
+            ])?;
+
+        // Step 2: window functions over the gap-free series
+        let by_date = || vec![col("pickup_date").sort(true, true)];
+        let mut projection = vec![col("pickup_date"), col("trip_count"), col("total_revenue")];
+        for days in ROLLING_DAYS {
+            let frame = WindowFrame::new_bounds(
+                WindowFrameUnits::Rows,
+                WindowFrameBound::Preceding(ScalarValue::UInt64(Some(days - 1))),
+                WindowFrameBound::CurrentRow,
+            );
+            for (column, prefix) in [("trip_count", "trip_count"), ("total_revenue", "revenue")] {
+                projection.push(
+                    over(avg_udaf(), column)
+                        .order_by(by_date())
+                        .window_frame(frame.clone())
+                        .build()?
+                        .alias(format!("{}_{}d_avg", prefix, days)),
+                );
+            }
+        }
+        let year = date_part(lit("year"), col("pickup_date"));
+        for (column, name) in [
+            ("trip_count", "ytd_trip_count"),
+            ("total_revenue", "ytd_revenue"),
+        ] {
+            projection.push(
+                over(sum_udaf(), column)
+                    .partition_by(vec![year.clone()])
+                    .order_by(by_date())
+                    .window_frame(WindowFrame::new_bounds(
+                        WindowFrameUnits::Rows,
+                        WindowFrameBound::Preceding(ScalarValue::UInt64(None)),
+                        WindowFrameBound::CurrentRow,
+                    ))
+                    .build()?
+                    .alias(name),
+            );
+        }
+        Ok(filled.select(projection)?)
+    }
+
+    fn sql(&self, input: &AggregationInput) -> String {
+        let rolling: Vec<String> = ROLLING_DAYS
+            .iter()
+            .flat_map(|days| {
+                [("trip_count", "trip_count"), ("total_revenue", "revenue")].map(
+                    |(column, prefix)| {
+                        format!(
+                            "AVG({column}) OVER (ORDER BY pickup_date ROWS BETWEEN {preceding} PRECEDING AND CURRENT ROW) AS {prefix}_{days}d_avg",
+                            preceding = days - 1,
+                        )
+                    },
+                )
+            })
+            .collect();
+
+        format!(
+            r#"
+            SELECT
+                pickup_date,
+                trip_count,
+                total_revenue,
+                {rolling},
+                SUM(trip_count) OVER (
+                    PARTITION BY date_part('year', pickup_date) ORDER BY pickup_date
+                    ROWS BETWEEN UNBOUNDED PRECEDING AND CURRENT ROW
+                ) AS ytd_trip_count,
+                SUM(total_revenue) OVER (
+                    PARTITION BY date_part('year', pickup_date) ORDER BY pickup_date
+                    ROWS BETWEEN UNBOUNDED PRECEDING AND CURRENT ROW
+                ) AS ytd_revenue
+            FROM (
+                SELECT
+                    c.day AS pickup_date,
+                    COALESCE(d.trip_count, 0) AS trip_count,
+                    COALESCE(d.total_revenue, 0.0) AS total_revenue
+                FROM {calendar} c
+                LEFT JOIN (
+                    SELECT
+                        date_trunc('day', pickup_datetime) AS pickup_date,
+                        COUNT(*) AS trip_count,
+                        SUM(total_amount) AS total_revenue
+                    FROM {table}
+                    {where_clause}
+                    GROUP BY 1
+                ) d ON c.day = d.pickup_date
+            ) filled
+        "#,
+            rolling = rolling.join(",\n"),
+            calendar = CALENDAR_TABLE,
+            table = input.table,
+            where_clause = input.where_sql(),
+        )
+    }
+
+    fn sort_order(&self) -> Vec<SortExpr> {
+        vec![col("pickup_date").sort(true, true)]
+    }
+}
@@ -0,0 +1,347 @@
+use super::{Aggregation, AggregationInput};
+use crate::codes::CodeTable;
+use anyhow::Result;
+use clap::ValueEnum;
+use datafusion::functions::datetime::expr_fn::date_trunc;
+use datafusion::functions_aggregate::approx_percentile_cont::approx_percentile_cont;
+use datafusion::functions_aggregate::expr_fn::{
+    approx_median, avg, count, max, median, min, stddev,
+};
+use datafusion::functions_aggregate::percentile_cont::percentile_cont;
+use datafusion::functions_nested::expr_fn::make_array;
+use datafusion::logical_expr::SortExpr;
+use datafusion::prelude::*;
+
+/// How percentiles (median, p90, p95, p99) are computed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum PercentileMode {
+    /// Exact `percentile_cont` / `median` (sorts every group in memory)
+    Exact,
+    /// `approx_percentile_cont` / `approx_median` (t-digest, bounded memory)
+    Approx,
+}
+
+/// A trip measure whose distribution is analyzed
+struct Metric {
+    name: &'static str,
+    /// Expression over the trip table (DataFrame API)
+    expr: fn() -> Result<Expr>,
+    /// The same expression in SQL
+    sql: &'static str,
+    /// Histogram bucket width
+    bucket_width: f64,
+    /// Values at or above this go into one open-ended last bucket
+    bucket_cap: f64,
+}
+
+const METRICS: [Metric; 4] = [
+    Metric {
+        name: "fare_amount",
+        expr: || Ok(col("fare_amount")),
+        sql: "fare_amount",
+        bucket_width: 5.0,
+        bucket_cap: 200.0,
+    },
+    Metric {
+        name: "tip_amount",
+        expr: || Ok(col("tip_amount")),
+        sql: "tip_amount",
+        bucket_width: 1.0,
+        bucket_cap: 50.0,
+    },
+    Metric {
+        name: "trip_distance",
+        expr: || Ok(col("trip_distance")),
+        sql: "trip_distance",
+        bucket_width: 1.0,
+        bucket_cap: 50.0,
+    },
+    // Tip as a percentage of the fare, NULL for zero/negative fares
+    Metric {
+        name: "tip_pct",
+        expr: || {
+            Ok(when(
+                col("fare_amount").gt(lit(0.0)),
+                col("tip_amount") / col("fare_amount") * lit(100.0),
+            )
+            .end()?)
+        },
+        sql: "CASE WHEN fare_amount > 0 THEN tip_amount / fare_amount * 100.0 END",
+        bucket_width: 5.0,
+        bucket_cap: 100.0,
+    },
+];
+
+/// Grouping of [`FareTipStats`] and [`FareTipHistogram`]
+#[derive(Clone, Copy, Debug)]
+pub enum Grain {
+    Month,
+    PaymentType,
+}
+
+impl Grain {
+    fn column(self) -> &'static str {
+        match self {
+            Grain::Month => "pickup_month",
+            Grain::PaymentType => "payment_type",
+        }
+    }
+
+    fn expr(self, input: &AggregationInput) -> Result<Expr> {
+        Ok(match self {
+            Grain::Month => input
+                .window
+                .bucket(date_trunc(lit("month"), col("pickup_datetime")))?,
+            Grain::PaymentType => col("payment_type"),
+        }
+        .alias(self.column()))
+    }
+
+    fn sql(self, input: &AggregationInput) -> String {
+        match self {
+            Grain::Month => input
+                .window
+                .bucket_sql("date_trunc('month', pickup_datetime)"),
+            Grain::PaymentType => "payment_type".to_string(),
+        }
+    }
+
+    fn code_columns(self) -> Vec<(&'static str, CodeTable)> {
+        match self {
+            Grain::Month => Vec::new(),
+            Grain::PaymentType => vec![("payment_type", CodeTable::PaymentType)],
+        }
+    }
+}
+
+/// One `(group, metric, value)` row per trip and metric (DataFrame API).
+///
+/// The metrics are unpivoted by unnesting two parallel arrays, so the trip table is
+/// scanned once rather than once per metric.
+fn metric_values(input: &AggregationInput, grain: Grain) -> Result<DataFrame> {
+    let names = METRICS.iter().map(|m| lit(m.name)).collect();
+    let values = METRICS
+        .iter()
+        .map(|m| (m.expr)())
+        .collect::<Result<Vec<_>>>()?;
+    Ok(input
+        .trips
+        .clone()
+        .select(vec![
+            grain.expr(input)?,
+            make_array(names).alias("metric"),
+            make_array(values).alias("value"),
+        ])?
+        .unnest_columns(&["metric", "value"])?)
+}
+
+/// SQL equivalent of [`metric_values`]
+fn metric_values_sql(input: &AggregationInput, grain: Grain) -> String {
+    let names: Vec<String> = METRICS.iter().map(|m| format!("'{}'", m.name)).collect();
+    let values: Vec<&str> = METRICS.iter().map(|m| m.sql).collect();
+    format!(
+        "SELECT {group} AS {group_column}, unnest(make_array({names})) AS metric, \
+         unnest(make_array({values})) AS value FROM {table} {where_clause}",
+        group = grain.sql(input),
+        group_column = grain.column(),
+        names = names.join(", "),
+        values = values.join(", "),
+        table = input.table,
+        where_clause = input.where_sql(),
+    )
+}
+
+/// Mean, standard deviation, median and p90/p95/p99 of fares, tips, distance and tip %
+pub struct FareTipStats(pub Grain);
+
+impl Aggregation for FareTipStats {
+    fn name(&self) -> &'static str {
+        match self.0 {
+            Grain::Month => "fare_tip_stats_by_month",
+            Grain::PaymentType => "fare_tip_stats_by_payment_type",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self.0 {
+            Grain::Month => "Fare, tip and distance percentiles by pickup month",
+            Grain::PaymentType => "Fare, tip and distance percentiles by payment type",
+        }
+    }
+
+    fn code_columns(&self) -> Vec<(&'static str, CodeTable)> {
+        self.0.code_columns()
+    }
+
+    fn dataframe(&self, input: &AggregationInput) -> Result<DataFrame> {
+        let value = || col("value");
+        let percentile = |p: f64| match input.percentiles {
+            PercentileMode::Exact => percentile_cont(value().sort(true, false), lit(p)),
+            PercentileMode::Approx => {
+                approx_percentile_cont(value().sort(true, false), lit(p), None)
+            }
+        };
+        let median = match input.percentiles {
+            PercentileMode::Exact => median(value()),
+            PercentileMode::Approx => approx_median(value()),
+        };
+
+        Ok(metric_values(input, self.0)?.aggregate(
+            vec![col(self.0.column()), col("metric")],
+            vec![
+                count(value()).alias("value_count"),
+                avg(value()).alias("mean"),
+                stddev(value()).alias("stddev"),
+                min(value()).alias("min"),
+                median.alias("median"),
+                percentile(0.90).alias("p90"),
+                percentile(0.95).alias("p95"),
+                percentile(0.99).alias("p99"),
+                max(value()).alias("max"),
+            ],
+        )?)
+    }
+
+    fn sql(&self, input: &AggregationInput) -> String {
+        let (median, percentile) = match input.percentiles {
+            PercentileMode::Exact => ("MEDIAN", "percentile_cont"),
+            PercentileMode::Approx => ("APPROX_MEDIAN", "approx_percentile_cont"),
+        };
+        format!(
+            r#"
+            SELECT
+                {group_column},
+                metric,
+                COUNT(value) AS value_count,
+                AVG(value) AS mean,
+                STDDEV(value) AS stddev,
+                MIN(value) AS min,
+                {median}(value) AS median,
+                {percentile}(0.90) WITHIN GROUP (ORDER BY value) AS p90,
+                {percentile}(0.95) WITHIN GROUP (ORDER BY value) AS p95,
+                {percentile}(0.99) WITHIN GROUP (ORDER BY value) AS p99,
+                MAX(value) AS max
+            FROM (
+                {values}
+            ) v
+            GROUP BY 1, 2
+        "#,
+            group_column = self.0.column(),
+            values = metric_values_sql(input, self.0),
+        )
+    }
+
+    fn sort_order(&self) -> Vec<SortExpr> {
+        vec![
+            col(self.0.column()).sort(true, true),
+            col("metric").sort(true, true),
+        ]
+    }
+
+    /// t-digest results depend on the order partial states are merged in, which differs
+    /// between runs
+    fn approximate_columns(&self, input: &AggregationInput) -> Vec<&'static str> {
+        match input.percentiles {
+            PercentileMode::Exact => Vec::new(),
+            PercentileMode::Approx => vec!["median", "p90", "p95", "p99"],
+        }
+    }
+}
+
+/// Fixed-width histogram of fares, tips, distance and tip %, with an open-ended last
+/// bucket (see [`Metric::bucket_cap`]); negative values get their own buckets below 0
+pub struct FareTipHistogram(pub Grain);
+
+impl Aggregation for FareTipHistogram {
+    fn name(&self) -> &'static str {
+        match self.0 {
+            Grain::Month => "fare_tip_histogram_by_month",
+            Grain::PaymentType => "fare_tip_histogram_by_payment_type",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self.0 {
+            Grain::Month => "Fare, tip and distance histograms by pickup month",
+            Grain::PaymentType => "Fare, tip and distance histograms by payment type",
+        }
+    }
+
+    fn code_columns(&self) -> Vec<(&'static str, CodeTable)> {
+        self.0.code_columns()
+    }
+
+    fn dataframe(&self, input: &AggregationInput) -> Result<DataFrame> {
+        // bucket_start: the cap for values at or above it, else value rounded down to the width
+        let mut branches = METRICS.iter().flat_map(|m| {
+            let is_metric = col("metric").eq(lit(m.name));
+            [
+                (
+                    is_metric.clone().and(col("value").gt_eq(lit(m.bucket_cap))),
+                    lit(m.bucket_cap),
+                ),
+                (
+                    is_metric,
+                    floor(col("value") / lit(m.bucket_width)) * lit(m.bucket_width),
+                ),
+            ]
+        });
+        let (first_when, first_then) = branches.next().expect("METRICS is not empty");
+        let mut bucket = when(first_when, first_then);
+        for (when_expr, then_expr) in branches {
+            bucket.when(when_expr, then_expr);
+        }
+
+        Ok(metric_values(input, self.0)?
+            .filter(col("value").is_not_null())?
+            .aggregate(
+                vec![
+                    col(self.0.column()),
+                    col("metric"),
+                    bucket.end()?.alias("bucket_start"),
+                ],
+                vec![count(lit(1)).alias("trip_count")],
+            )?)
+    }
+
+    fn sql(&self, input: &AggregationInput) -> String {
+        let cases: Vec<String> = METRICS
+            .iter()
+            .map(|m| {
+                format!(
+                    "WHEN metric = '{name}' AND value >= {cap:.1} THEN {cap:.1} \
+                     WHEN metric = '{name}' THEN floor(value / {width:.1}) * {width:.1}",
+                    name = m.name,
+                    cap = m.bucket_cap,
+                    width = m.bucket_width,
+                )
+            })
+            .collect();
+
+        format!(
+            r#"
+            SELECT
+                {group_column},
+                metric,
+                CASE {cases} END AS bucket_start,
+                COUNT(*) AS trip_count
+            FROM (
+                {values}
+            ) v
+            WHERE value IS NOT NULL
+            GROUP BY 1, 2, 3
+        "#,
+            group_column = self.0.column(),
+            cases = cases.join(" "),
+            values = metric_values_sql(input, self.0),
+        )
+    }
+
+    fn sort_order(&self) -> Vec<SortExpr> {
+        vec![
+            col(self.0.column()).sort(true, true),
+            col("metric").sort(true, true),
+            col("bucket_start").sort(true, true),
+        ]
+    }
+}
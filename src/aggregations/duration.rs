@@ -0,0 +1,169 @@
+use super::{Aggregation, AggregationInput};
+use crate::zones::{zones_as, ZONES_TABLE};
+use anyhow::Result;
+use datafusion::arrow::datatypes::DataType;
+use datafusion::functions::datetime::expr_fn::{date_part, date_trunc};
+use datafusion::functions_aggregate::expr_fn::{avg, count, median, sum};
+use datafusion::logical_expr::SortExpr;
+use datafusion::prelude::*;
+
+/// Trips lasting this long or longer are treated as multi-day outliers
+pub const MULTI_DAY_MINUTES: f64 = 24.0 * 60.0;
+
+/// Grouping of [`DurationSpeed`]
+#[derive(Clone, Copy, Debug)]
+pub enum Grain {
+    Month,
+    Hour,
+    PickupBorough,
+}
+
+/// Trip duration and average speed distribution.
+///
+/// Zero-length (duration <= 0) and multi-day (>= 24h) trips are counted separately and
+/// left out of the duration/speed statistics.
+pub struct DurationSpeed(pub Grain);
+
+impl DurationSpeed {
+    /// `0 < trip_duration_minutes < 24h`
+    fn valid() -> Expr {
+        col("trip_duration_minutes")
+            .gt(lit(0.0))
+            .and(col("trip_duration_minutes").lt(lit(MULTI_DAY_MINUTES)))
+    }
+
+    fn valid_sql() -> String {
+        format!(
+            "t.trip_duration_minutes > 0 AND t.trip_duration_minutes < {:.1}",
+            MULTI_DAY_MINUTES
+        )
+    }
+
+    fn group_column(&self) -> &'static str {
+        match self.0 {
+            Grain::Month => "pickup_month",
+            Grain::Hour => "pickup_hour",
+            Grain::PickupBorough => "pickup_borough",
+        }
+    }
+}
+
+impl Aggregation for DurationSpeed {
+    fn name(&self) -> &'static str {
+        match self.0 {
+            Grain::Month => "duration_speed_by_month",
+            Grain::Hour => "duration_speed_by_hour",
+            Grain::PickupBorough => "duration_speed_by_pickup_borough",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self.0 {
+            Grain::Month => "Trip duration and average speed by pickup month",
+            Grain::Hour => "Trip duration and average speed by pickup hour",
+            Grain::PickupBorough => "Trip duration and average speed by pickup borough",
+        }
+    }
+
+    fn requires_zones(&self) -> bool {
+        matches!(self.0, Grain::PickupBorough)
+    }
+
+    fn dataframe(&self, input: &AggregationInput) -> Result<DataFrame> {
+        let (trips, group) = match self.0 {
+            Grain::Month => (
+                input.trips.clone(),
+                input
+                    .window
+                    .bucket(date_trunc(lit("month"), col("pickup_datetime")))?,
+            ),
+            Grain::Hour => (
+                input.trips.clone(),
+                input.window.bucket(cast(
+                    date_part(lit("hour"), col("pickup_datetime")),
+                    DataType::Int64,
+                ))?,
+            ),
+            Grain::PickupBorough => (
+                input.trips.clone().join(
+                    zones_as(input.zones(self.name())?, "pickup_")?,
+                    JoinType::Left,
+                    &["pu_location_id"],
+                    &["pickup_location_id"],
+                    None,
+                )?,
+                col("pickup_borough"),
+            ),
+        };
+
+        let when_valid = |column: &str| when(Self::valid(), col(column)).end();
+        let flag = |predicate: Expr| -> Result<Expr> {
+            Ok(sum(when(predicate, lit(1i64)).otherwise(lit(0i64))?))
+        };
+
+        Ok(trips.aggregate(
+            vec![group.alias(self.group_column())],
+            vec![
+                count(lit(1)).alias("trip_count"),
+                flag(col("trip_duration_minutes").lt_eq(lit(0.0)))?.alias("zero_length_trips"),
+                flag(col("trip_duration_minutes").gt_eq(lit(MULTI_DAY_MINUTES)))?
+                    .alias("multi_day_trips"),
+                avg(when_valid("trip_duration_minutes")?).alias("avg_duration_minutes"),
+                median(when_valid("trip_duration_minutes")?).alias("median_duration_minutes"),
+                avg(when_valid("avg_speed_mph")?).alias("avg_speed_mph"),
+                median(when_valid("avg_speed_mph")?).alias("median_speed_mph"),
+            ],
+        )?)
+    }
+
+    fn sql(&self, input: &AggregationInput) -> String {
+        let (group, join) = match self.0 {
+            Grain::Month => (
+                input
+                    .window
+                    .bucket_sql("date_trunc('month', t.pickup_datetime)"),
+                String::new(),
+            ),
+            Grain::Hour => (
+                input
+                    .window
+                    .bucket_sql("CAST(date_part('hour', t.pickup_datetime) AS BIGINT)"),
+                String::new(),
+            ),
+            Grain::PickupBorough => (
+                "pz.borough".to_string(),
+                format!(
+                    "LEFT JOIN {} pz ON t.pu_location_id = pz.location_id",
+                    ZONES_TABLE
+                ),
+            ),
+        };
+
+        format!(
+            r#"
+            SELECT
+                {group} AS {group_column},
+                COUNT(*) AS trip_count,
+                SUM(CASE WHEN t.trip_duration_minutes <= 0 THEN 1 ELSE 0 END) AS zero_length_trips,
+                SUM(CASE WHEN t.trip_duration_minutes >= {multi_day:.1} THEN 1 ELSE 0 END) AS multi_day_trips,
+                AVG(CASE WHEN {valid} THEN t.trip_duration_minutes END) AS avg_duration_minutes,
+                MEDIAN(CASE WHEN {valid} THEN t.trip_duration_minutes END) AS median_duration_minutes,
+                AVG(CASE WHEN {valid} THEN t.avg_speed_mph END) AS avg_speed_mph,
+                MEDIAN(CASE WHEN {valid} THEN t.avg_speed_mph END) AS median_speed_mph
+            FROM {table} t
+            {join}
+            {where_clause}
+            GROUP BY 1
+        "#,
+            group_column = self.group_column(),
+            multi_day = MULTI_DAY_MINUTES,
+            valid = Self::valid_sql(),
+            table = input.table,
+            where_clause = input.where_sql(),
+        )
+    }
+
+    fn sort_order(&self) -> Vec<SortExpr> {
+        vec![col(self.group_column()).sort(true, true)]
+    }
+}
@@ -0,0 +1,240 @@
+use super::{Aggregation, AggregationInput};
+use crate::zones::{zones_as, ZONES_TABLE};
+use anyhow::Result;
+use datafusion::arrow::datatypes::{DataType, TimeUnit};
+use datafusion::functions::datetime::expr_fn::date_trunc;
+use datafusion::functions_aggregate::expr_fn::{avg, count, sum};
+use datafusion::logical_expr::SortExpr;
+use datafusion::prelude::*;
+
+/// Start of Manhattan congestion pricing (CBD congestion relief zone toll)
+const CONGESTION_PRICING_START: &str = "2025-01-05 00:00:00";
+
+/// Every surcharge, tax, toll and fee added on top of the fare: with the fare and the tip
+/// they make up `total_amount`
+const FEE_COLUMNS: [&str; 7] = [
+    "extra",
+    "mta_tax",
+    "improvement_surcharge",
+    "tolls_amount",
+    "congestion_surcharge",
+    "cbd_congestion_fee",
+    "airport_fee",
+];
+
+/// Fees reported with their own totals
+const PRICING_FEE_COLUMNS: [&str; 3] =
+    ["congestion_surcharge", "cbd_congestion_fee", "airport_fee"];
+
+/// Grouping of [`Fees`]
+#[derive(Clone, Copy, Debug)]
+pub enum Grain {
+    Month,
+    PickupZone,
+    DropoffZone,
+}
+
+/// Congestion surcharge, CBD congestion fee and airport fee totals, and the share of all
+/// fees versus the fare in `total_amount`, before and after congestion pricing started
+pub struct Fees(pub Grain);
+
+impl Fees {
+    fn group_columns(&self) -> Vec<&'static str> {
+        match self.0 {
+            Grain::Month => vec!["pickup_month"],
+            Grain::PickupZone => vec!["pickup_borough", "pickup_zone"],
+            Grain::DropoffZone => vec!["dropoff_borough", "dropoff_zone"],
+        }
+    }
+}
+
+impl Aggregation for Fees {
+    fn name(&self) -> &'static str {
+        match self.0 {
+            Grain::Month => "fees_by_month",
+            Grain::PickupZone => "fees_by_pickup_zone",
+            Grain::DropoffZone => "fees_by_dropoff_zone",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self.0 {
+            Grain::Month => {
+                "Surcharges and fees vs fare by pickup month, before/after congestion pricing"
+            }
+            Grain::PickupZone => {
+                "Surcharges and fees vs fare by pickup zone, before/after congestion pricing"
+            }
+            Grain::DropoffZone => {
+                "Surcharges and fees vs fare by dropoff zone, before/after congestion pricing"
+            }
+        }
+    }
+
+    fn requires_zones(&self) -> bool {
+        !matches!(self.0, Grain::Month)
+    }
+
+    fn dataframe(&self, input: &AggregationInput) -> Result<DataFrame> {
+        let (trips, mut group) = match self.0 {
+            Grain::Month => (
+                input.trips.clone(),
+                vec![input
+                    .window
+                    .bucket(date_trunc(lit("month"), col("pickup_datetime")))?
+                    .alias("pickup_month")],
+            ),
+            Grain::PickupZone | Grain::DropoffZone => {
+                let (prefix, location) = match self.0 {
+                    Grain::PickupZone => ("pickup_", "pu_location_id"),
+                    _ => ("dropoff_", "do_location_id"),
+                };
+                let trips = input.trips.clone().join(
+                    zones_as(input.zones(self.name())?, prefix)?,
+                    JoinType::Left,
+                    &[location],
+                    &[format!("{}location_id", prefix).as_str()],
+                    None,
+                )?;
+                (trips, self.group_columns().into_iter().map(col).collect())
+            }
+        };
+
+        // pricing_period = 'before' / 'after' the congestion pricing start
+        let start = cast(
+            lit(CONGESTION_PRICING_START),
+            DataType::Timestamp(TimeUnit::Nanosecond, None),
+        );
+        group.push(
+            when(col("pickup_datetime").lt(start.clone()), lit("before"))
+                .when(col("pickup_datetime").gt_eq(start), lit("after"))
+                .end()?
+                .alias("pricing_period"),
+        );
+
+        let fees = FEE_COLUMNS
+            .iter()
+            .map(|c| coalesce(vec![col(*c), lit(0.0)]))
+            .reduce(|acc, e| acc + e)
+            .expect("FEE_COLUMNS is not empty");
+
+        // Step 1: aggregate sums separately
+        let mut aggregates = vec![
+            count(lit(1)).alias("trip_count"),
+            sum(col("fare_amount")).alias("fare_total"),
+        ];
+        aggregates.extend(
+            PRICING_FEE_COLUMNS
+                .iter()
+                .map(|c| sum(col(*c)).alias(format!("{}_total", c))),
+        );
+        aggregates.extend([
+            sum(fees).alias("fees_total"),
+            sum(col("total_amount")).alias("amount_total"),
+            sum(when(col("cbd_congestion_fee").gt(lit(0.0)), lit(1i64)).otherwise(lit(0i64))?)
+                .alias("cbd_fee_trips"),
+            avg(col("cbd_congestion_fee")).alias("avg_cbd_congestion_fee"),
+        ]);
+        let base = trips.aggregate(group, aggregates)?;
+
+        // Step 2: shares of total_amount in a projection
+        let mut projection: Vec<Expr> = self.group_columns().into_iter().map(col).collect();
+        projection.push(col("pricing_period"));
+        projection.extend(
+            [
+                "trip_count",
+                "fare_total",
+                "congestion_surcharge_total",
+                "cbd_congestion_fee_total",
+                "airport_fee_total",
+                "fees_total",
+                "amount_total",
+            ]
+            .map(col),
+        );
+        projection.extend([
+            (col("fees_total") / col("amount_total")).alias("fee_share"),
+            (col("fare_total") / col("amount_total")).alias("fare_share"),
+            col("cbd_fee_trips"),
+            col("avg_cbd_congestion_fee"),
+        ]);
+        Ok(base.select(projection)?)
+    }
+
+    fn sql(&self, input: &AggregationInput) -> String {
+        let (group, join) = match self.0 {
+            Grain::Month => (
+                format!(
+                    "{} AS pickup_month",
+                    input
+                        .window
+                        .bucket_sql("date_trunc('month', t.pickup_datetime)")
+                ),
+                String::new(),
+            ),
+            Grain::PickupZone => (
+                "pz.borough AS pickup_borough, pz.zone AS pickup_zone".to_string(),
+                format!(
+                    "LEFT JOIN {} pz ON t.pu_location_id = pz.location_id",
+                    ZONES_TABLE
+                ),
+            ),
+            Grain::DropoffZone => (
+                "dz.borough AS dropoff_borough, dz.zone AS dropoff_zone".to_string(),
+                format!(
+                    "LEFT JOIN {} dz ON t.do_location_id = dz.location_id",
+                    ZONES_TABLE
+                ),
+            ),
+        };
+        let fees = FEE_COLUMNS
+            .iter()
+            .map(|c| format!("COALESCE(t.{}, 0)", c))
+            .collect::<Vec<_>>()
+            .join(" + ");
+
+        format!(
+            r#"
+            SELECT
+                {group},
+                CASE
+                    WHEN t.pickup_datetime < TIMESTAMP '{start}' THEN 'before'
+                    WHEN t.pickup_datetime >= TIMESTAMP '{start}' THEN 'after'
+                END AS pricing_period,
+                COUNT(*) AS trip_count,
+                SUM(t.fare_amount) AS fare_total,
+                SUM(t.congestion_surcharge) AS congestion_surcharge_total,
+                SUM(t.cbd_congestion_fee) AS cbd_congestion_fee_total,
+                SUM(t.airport_fee) AS airport_fee_total,
+                SUM({fees}) AS fees_total,
+                SUM(t.total_amount) AS amount_total,
+                SUM({fees}) / SUM(t.total_amount) AS fee_share,
+                SUM(t.fare_amount) / SUM(t.total_amount) AS fare_share,
+                SUM(CASE WHEN t.cbd_congestion_fee > 0 THEN 1 ELSE 0 END) AS cbd_fee_trips,
+                AVG(t.cbd_congestion_fee) AS avg_cbd_congestion_fee
+            FROM {table} t
+            {join}
+            {where_clause}
+            GROUP BY {group_by}
+        "#,
+            start = CONGESTION_PRICING_START,
+            table = input.table,
+            where_clause = input.where_sql(),
+            group_by = (1..=self.group_columns().len() + 1)
+                .map(|i| i.to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+        )
+    }
+
+    fn sort_order(&self) -> Vec<SortExpr> {
+        let mut order: Vec<SortExpr> = self
+            .group_columns()
+            .into_iter()
+            .map(|c| col(c).sort(true, true))
+            .collect();
+        // 'before' ahead of 'after'
+        order.push(col("pricing_period").sort(false, true));
+        order
+    }
+}
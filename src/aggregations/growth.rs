@@ -0,0 +1,215 @@
+use super::{Aggregation, AggregationInput};
+use anyhow::Result;
+use datafusion::arrow::datatypes::DataType;
+use datafusion::functions::datetime::expr_fn::{date_part, date_trunc};
+use datafusion::functions_aggregate::expr_fn::{count, sum};
+use datafusion::functions_window::expr_fn::lag;
+use datafusion::logical_expr::{ExprFunctionExt, SortExpr};
+use datafusion::prelude::*;
+
+/// Measures compared month over month and year over year: (column, prefix of the change columns)
+const MEASURES: [(&str, &str); 2] = [("trip_count", "trip_count"), ("total_revenue", "revenue")];
+
+/// Month-over-month and year-over-year change of trips and revenue.
+///
+/// MoM compares with the previous calendar month and is NULL when that month has no trips.
+/// YoY compares with the same calendar month of the previous analyzed year (`yoy_base_year`),
+/// so `--years 2019,2025` compares 2025 with 2019. The NULL bucket of `--window isolate` is
+/// left out.
+pub struct GrowthByMonth;
+
+/// `(current - previous)` and `(current - previous) / previous * 100`
+fn change(current: &str, previous: Expr, prefix: &str, period: &str) -> [Expr; 2] {
+    let as_float = |e: Expr| cast(e, DataType::Float64);
+    [
+        (col(current) - previous.clone()).alias(format!("{}_{}_change", prefix, period)),
+        ((as_float(col(current)) - as_float(previous.clone()))
+            / nullif(as_float(previous), lit(0.0))
+            * lit(100.0))
+        .alias(format!("{}_{}_pct", prefix, period)),
+    ]
+}
+
+fn change_sql(current: &str, previous: &str, prefix: &str, period: &str) -> String {
+    format!(
+        "{current} - {previous} AS {prefix}_{period}_change,\n\
+         (CAST({current} AS DOUBLE) - CAST({previous} AS DOUBLE)) / NULLIF(CAST({previous} AS DOUBLE), 0.0) * 100.0 AS {prefix}_{period}_pct",
+    )
+}
+
+impl Aggregation for GrowthByMonth {
+    fn name(&self) -> &'static str {
+        "growth_by_month"
+    }
+
+    fn description(&self) -> &'static str {
+        "Month-over-month and year-over-year change of trips and revenue"
+    }
+
+    fn dataframe(&self, input: &AggregationInput) -> Result<DataFrame> {
+        let monthly = input
+            .trips
+            .clone()
+            .aggregate(
+                vec![input
+                    .window
+                    .bucket(date_trunc(lit("month"), col("pickup_datetime")))?
+                    .alias("pickup_month")],
+                vec![
+                    count(lit(1)).alias("trip_count"),
+                    sum(col("total_amount")).alias("total_revenue"),
+                ],
+            )?
+            .filter(col("pickup_month").is_not_null())?
+            .with_column(
+                "pickup_year",
+                cast(date_part(lit("year"), col("pickup_month")), DataType::Int64),
+            )?
+            .with_column(
+                "calendar_month",
+                cast(
+                    date_part(lit("month"), col("pickup_month")),
+                    DataType::Int64,
+                ),
+            )?;
+
+        // Step 1: previous month (in month order) and same month of the previous year
+        let month_index = || col("pickup_year") * lit(12i64) + col("calendar_month");
+        let previous_month = |e: Expr| -> Result<Expr> {
+            Ok(lag(e, Some(1), None)
+                .order_by(vec![col("pickup_month").sort(true, true)])
+                .build()?)
+        };
+        let previous_year = |e: Expr| -> Result<Expr> {
+            Ok(lag(e, Some(1), None)
+                .partition_by(vec![col("calendar_month")])
+                .order_by(vec![col("pickup_year").sort(true, true)])
+                .build()?)
+        };
+        let mut lagged = vec![
+            col("pickup_month"),
+            col("pickup_year"),
+            col("calendar_month"),
+            col("trip_count"),
+            col("total_revenue"),
+            (month_index() - previous_month(month_index())?).alias("months_since_previous"),
+            previous_year(col("pickup_year"))?.alias("yoy_base_year"),
+        ];
+        for (column, _) in MEASURES {
+            lagged.push(previous_month(col(column))?.alias(format!("mom_{}", column)));
+            lagged.push(previous_year(col(column))?.alias(format!("yoy_{}", column)));
+        }
+        let lagged = monthly.select(lagged)?;
+
+        // Step 2: absolute and percentage change, MoM only against the adjacent month
+        let mut projection = vec![
+            col("pickup_month"),
+            col("pickup_year"),
+            col("calendar_month"),
+            col("trip_count"),
+            col("total_revenue"),
+        ];
+        for (column, prefix) in MEASURES {
+            let previous = when(
+                col("months_since_previous").eq(lit(1i64)),
+                col(format!("mom_{}", column)),
+            )
+            .end()?;
+            projection.extend(change(column, previous, prefix, "mom"));
+        }
+        projection.push(col("yoy_base_year"));
+        for (column, prefix) in MEASURES {
+            projection.extend(change(
+                column,
+                col(format!("yoy_{}", column)),
+                prefix,
+                "yoy",
+            ));
+        }
+        Ok(lagged.select(projection)?)
+    }
+
+    fn sql(&self, input: &AggregationInput) -> String {
+        let previous_month = "OVER (ORDER BY pickup_month ASC NULLS FIRST)";
+        let previous_year =
+            "OVER (PARTITION BY calendar_month ORDER BY pickup_year ASC NULLS FIRST)";
+        let lagged: Vec<String> = MEASURES
+            .iter()
+            .flat_map(|(column, _)| {
+                [
+                    format!("LAG({column}) {previous_month} AS mom_{column}"),
+                    format!("LAG({column}) {previous_year} AS yoy_{column}"),
+                ]
+            })
+            .collect();
+        let mom: Vec<String> = MEASURES
+            .iter()
+            .map(|(column, prefix)| {
+                change_sql(
+                    column,
+                    &format!("CASE WHEN months_since_previous = 1 THEN mom_{column} END"),
+                    prefix,
+                    "mom",
+                )
+            })
+            .collect();
+        let yoy: Vec<String> = MEASURES
+            .iter()
+            .map(|(column, prefix)| change_sql(column, &format!("yoy_{column}"), prefix, "yoy"))
+            .collect();
+
+        format!(
+            r#"
+            WITH monthly AS (
+                SELECT
+                    pickup_month,
+                    CAST(date_part('year', pickup_month) AS BIGINT) AS pickup_year,
+                    CAST(date_part('month', pickup_month) AS BIGINT) AS calendar_month,
+                    trip_count,
+                    total_revenue
+                FROM (
+                    SELECT
+                        {pickup_month} AS pickup_month,
+                        COUNT(*) AS trip_count,
+                        SUM(total_amount) AS total_revenue
+                    FROM {table}
+                    {where_clause}
+                    GROUP BY 1
+                ) m
+                WHERE pickup_month IS NOT NULL
+            ),
+            lagged AS (
+                SELECT
+                    *,
+                    (pickup_year * 12 + calendar_month)
+                        - LAG(pickup_year * 12 + calendar_month) {previous_month} AS months_since_previous,
+                    LAG(pickup_year) {previous_year} AS yoy_base_year,
+                    {lagged}
+                FROM monthly
+            )
+            SELECT
+                pickup_month,
+                pickup_year,
+                calendar_month,
+                trip_count,
+                total_revenue,
+                {mom},
+                yoy_base_year,
+                {yoy}
+            FROM lagged
+        "#,
+            pickup_month = input
+                .window
+                .bucket_sql("date_trunc('month', pickup_datetime)"),
+            table = input.table,
+            where_clause = input.where_sql(),
+            lagged = lagged.join(",\n"),
+            mom = mom.join(",\n"),
+            yoy = yoy.join(",\n"),
+        )
+    }
+
+    fn sort_order(&self) -> Vec<SortExpr> {
+        vec![col("pickup_month").sort(true, true)]
+    }
+}
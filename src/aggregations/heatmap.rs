@@ -0,0 +1,125 @@
+use super::{Aggregation, AggregationInput};
+use anyhow::{anyhow, Result};
+use datafusion::arrow::array::{Array, ArrayRef, Int64Array, RecordBatch, StringArray};
+use datafusion::arrow::datatypes::{DataType, Field, Schema};
+use datafusion::functions::datetime::expr_fn::date_part;
+use datafusion::functions_aggregate::expr_fn::{avg, count, median};
+use datafusion::logical_expr::SortExpr;
+use datafusion::prelude::*;
+use std::sync::Arc;
+
+const DAY_NAMES: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+/// Trips by pickup hour of day and day of week.
+///
+/// TLC timestamps are naive New York wall-clock times, so hour and weekday are taken
+/// as-is (they already are America/New_York local time, DST included).
+pub struct DemandHeatmap;
+
+impl Aggregation for DemandHeatmap {
+    fn name(&self) -> &'static str {
+        "demand_heatmap"
+    }
+
+    fn description(&self) -> &'static str {
+        "Trips, median fare and duration by pickup weekday and hour"
+    }
+
+    fn dataframe(&self, input: &AggregationInput) -> Result<DataFrame> {
+        // pickup_dow: 0 = Monday .. 6 = Sunday
+        let pickup_dow = input
+            .window
+            .bucket(cast(
+                date_part(lit("isodow"), col("pickup_datetime")),
+                DataType::Int64,
+            ))?
+            .alias("pickup_dow");
+        let pickup_hour = input
+            .window
+            .bucket(cast(
+                date_part(lit("hour"), col("pickup_datetime")),
+                DataType::Int64,
+            ))?
+            .alias("pickup_hour");
+
+        Ok(input.trips.clone().aggregate(
+            vec![pickup_dow, pickup_hour],
+            vec![
+                count(lit(1)).alias("trip_count"),
+                median(col("fare_amount")).alias("median_fare"),
+                avg(col("trip_duration_minutes")).alias("avg_duration_minutes"),
+            ],
+        )?)
+    }
+
+    fn sql(&self, input: &AggregationInput) -> String {
+        format!(
+            r#"
+            SELECT
+                {pickup_dow} AS pickup_dow,
+                {pickup_hour} AS pickup_hour,
+                COUNT(*) AS trip_count,
+                MEDIAN(fare_amount) AS median_fare,
+                AVG(trip_duration_minutes) AS avg_duration_minutes
+            FROM {table}
+            {where_clause}
+            GROUP BY 1, 2
+        "#,
+            pickup_dow = input
+                .window
+                .bucket_sql("CAST(date_part('isodow', pickup_datetime) AS BIGINT)"),
+            pickup_hour = input
+                .window
+                .bucket_sql("CAST(date_part('hour', pickup_datetime) AS BIGINT)"),
+            table = input.table,
+            where_clause = input.where_sql(),
+        )
+    }
+
+    fn sort_order(&self) -> Vec<SortExpr> {
+        vec![
+            col("pickup_dow").sort(true, true),
+            col("pickup_hour").sort(true, true),
+        ]
+    }
+
+    /// Trip counts pivoted to one row per weekday and one column per hour (`h00`..`h23`)
+    fn grid(&self, batches: &[RecordBatch]) -> Result<Option<RecordBatch>> {
+        let mut counts = [[0i64; 24]; 7];
+        for batch in batches {
+            let column = |name: &str| {
+                batch
+                    .column_by_name(name)
+                    .and_then(|c| c.as_any().downcast_ref::<Int64Array>())
+                    .ok_or_else(|| anyhow!("demand_heatmap result has no Int64 {}", name))
+            };
+            let (dow, hour, trips) = (
+                column("pickup_dow")?,
+                column("pickup_hour")?,
+                column("trip_count")?,
+            );
+            for row in 0..batch.num_rows() {
+                // Out-of-window trips (isolate mode) have no weekday/hour cell
+                if dow.is_null(row) || hour.is_null(row) {
+                    continue;
+                }
+                counts[dow.value(row) as usize][hour.value(row) as usize] += trips.value(row);
+            }
+        }
+
+        let mut fields = vec![Field::new("pickup_day", DataType::Utf8, false)];
+        fields.extend((0..24).map(|h| Field::new(format!("h{:02}", h), DataType::Int64, false)));
+
+        let mut columns: Vec<ArrayRef> = vec![Arc::new(StringArray::from(DAY_NAMES.to_vec()))];
+        columns.extend((0..24).map(|h| {
+            Arc::new(Int64Array::from_iter_values(
+                counts.iter().map(|day| day[h]),
+            )) as ArrayRef
+        }));
+
+        Ok(Some(RecordBatch::try_new(
+            Arc::new(Schema::new(fields)),
+            columns,
+        )?))
+    }
+}
@@ -0,0 +1,265 @@
+//! Aggregations computed with both the DataFrame API and SQL.
+//!
+//! To add an analysis, implement [`Aggregation`] in a new module and list it in
+//! [`registry`]; it then shows up in `--list` and can be selected with `--only`.
+//!
+//! Library users can call an aggregation directly, e.g.
+//! `monthly::TripsByMonth.labeled_dataframe(&input)` with an input from
+//! [`crate::session::TlcSession::aggregation_input`].
+
+use crate::codes::{label_dataframe, label_sql, CodeTable};
+use crate::output::OutputSink;
+use crate::parity::{compare_batches, ParityReport, Tolerance};
+use crate::window::TimeWindow;
+use anyhow::{anyhow, Result};
+use datafusion::arrow::array::RecordBatch;
+use datafusion::logical_expr::SortExpr;
+use datafusion::prelude::*;
+pub use distribution::PercentileMode;
+pub use duration::MULTI_DAY_MINUTES;
+use log::info;
+
+pub mod airport;
+pub mod borough;
+pub mod daily;
+pub mod distribution;
+pub mod duration;
+pub mod fees;
+pub mod growth;
+pub mod heatmap;
+pub mod monthly;
+pub mod payment;
+
+/// Tables and settings shared by every aggregation of a run
+pub struct AggregationInput {
+    pub ctx: SessionContext,
+    /// Name of the canonical trip table, for SQL
+    pub table: String,
+    /// Canonical trip table with the time window already applied
+    pub trips: DataFrame,
+    /// Taxi zone lookup, if it was registered
+    pub zones: Option<DataFrame>,
+    pub window: TimeWindow,
+    /// Add a `<column>_label` next to every coded column (`--labels`)
+    pub labels: bool,
+    /// Exact or approximate percentiles
+    pub percentiles: PercentileMode,
+}
+
+impl AggregationInput {
+    /// SQL `WHERE` clause equivalent of the window applied to [`AggregationInput::trips`]
+    pub fn where_sql(&self) -> String {
+        self.window.where_sql()
+    }
+
+    /// The zone lookup, or an error naming the aggregation that needs it
+    pub fn zones(&self, aggregation: &str) -> Result<DataFrame> {
+        self.zones
+            .clone()
+            .ok_or_else(|| anyhow!("{} needs the taxi zone lookup (--zones)", aggregation))
+    }
+}
+
+/// One analysis, implemented once with the DataFrame API and once in SQL
+pub trait Aggregation: Send + Sync {
+    /// Short identifier used by `--only` and in output file names
+    fn name(&self) -> &'static str;
+
+    /// One-line human-readable description
+    fn description(&self) -> &'static str;
+
+    /// Whether the aggregation joins the taxi zone lookup
+    fn requires_zones(&self) -> bool {
+        false
+    }
+
+    /// Coded output columns and their dictionary, labeled when `--labels` is given
+    fn code_columns(&self) -> Vec<(&'static str, CodeTable)> {
+        Vec::new()
+    }
+
+    /// DataFrame API implementation (unsorted)
+    fn dataframe(&self, input: &AggregationInput) -> Result<DataFrame>;
+
+    /// SQL implementation (unsorted, without `ORDER BY`)
+    fn sql(&self, input: &AggregationInput) -> String;
+
+    /// Sort order applied to both the DataFrame API and SQL results
+    fn sort_order(&self) -> Vec<SortExpr>;
+
+    /// Output columns that depend on execution order (e.g. t-digest percentiles), left out
+    /// of the parity check
+    fn approximate_columns(&self, _input: &AggregationInput) -> Vec<&'static str> {
+        Vec::new()
+    }
+
+    /// DataFrame API implementation in [`Aggregation::sort_order`]
+    fn sorted_dataframe(&self, input: &AggregationInput) -> Result<DataFrame> {
+        Ok(self.dataframe(input)?.sort(self.sort_order())?)
+    }
+
+    /// [`Aggregation::sorted_dataframe`] with a `<column>_label` next to every
+    /// [`Aggregation::code_columns`] when `input.labels` is set
+    fn labeled_dataframe(&self, input: &AggregationInput) -> Result<DataFrame> {
+        let mut df = self.dataframe(input)?;
+        if input.labels {
+            for (column, table) in self.code_columns() {
+                df = label_dataframe(&input.ctx, df, column, table)?;
+            }
+        }
+        Ok(df.sort(self.sort_order())?)
+    }
+
+    /// Optional wide view of the (sorted) DataFrame API result, emitted as `<name>_grid`
+    fn grid(&self, _batches: &[RecordBatch]) -> Result<Option<RecordBatch>> {
+        Ok(None)
+    }
+}
+
+/// Every available aggregation, in the order they run
+pub fn registry() -> Vec<Box<dyn Aggregation>> {
+    vec![
+        Box::new(monthly::TripsByMonth),
+        Box::new(growth::GrowthByMonth),
+        Box::new(daily::DailySeries),
+        Box::new(payment::TipsByPaymentType),
+        Box::new(borough::TripsByPickupZone),
+        Box::new(borough::TripsByBoroughPair),
+        Box::new(heatmap::DemandHeatmap),
+        Box::new(duration::DurationSpeed(duration::Grain::Month)),
+        Box::new(duration::DurationSpeed(duration::Grain::Hour)),
+        Box::new(duration::DurationSpeed(duration::Grain::PickupBorough)),
+        Box::new(distribution::FareTipStats(distribution::Grain::Month)),
+        Box::new(distribution::FareTipStats(distribution::Grain::PaymentType)),
+        Box::new(distribution::FareTipHistogram(distribution::Grain::Month)),
+        Box::new(distribution::FareTipHistogram(
+            distribution::Grain::PaymentType,
+        )),
+        Box::new(fees::Fees(fees::Grain::Month)),
+        Box::new(fees::Fees(fees::Grain::PickupZone)),
+        Box::new(fees::Fees(fees::Grain::DropoffZone)),
+        Box::new(airport::AirportTrips),
+    ]
+}
+
+/// Aggregations selected with `--only` (all of them if `only` is empty)
+pub fn select(only: &[String]) -> Result<Vec<Box<dyn Aggregation>>> {
+    let all = registry();
+    if only.is_empty() {
+        return Ok(all);
+    }
+
+    if let Some(unknown) = only
+        .iter()
+        .find(|n| !all.iter().any(|a| a.name() == n.as_str()))
+    {
+        let names: Vec<&str> = all.iter().map(|a| a.name()).collect();
+        return Err(anyhow!(
+            "Unknown aggregation '{}', available: {}",
+            unknown,
+            names.join(", ")
+        ));
+    }
+
+    Ok(all
+        .into_iter()
+        .filter(|a| only.iter().any(|n| n == a.name()))
+        .collect())
+}
+
+/// Run both implementations of `agg`, emit them to `sink` and compare them.
+///
+/// Returns the parity report unless `tolerance` is `None` (parity check disabled).
+pub async fn run_aggregation(
+    agg: &dyn Aggregation,
+    input: &AggregationInput,
+    sink: &OutputSink,
+    tolerance: Option<Tolerance>,
+) -> Result<Option<ParityReport>> {
+    info!(
+        "\n==============================\n{} (DataFrame API): {}\n==============================",
+        agg.name(),
+        agg.description()
+    );
+
+    let df = agg.labeled_dataframe(input)?;
+    let mut sql = agg.sql(input);
+    if input.labels {
+        for (column, table) in agg.code_columns() {
+            let columns: Vec<String> = input
+                .ctx
+                .sql(&sql)
+                .await?
+                .schema()
+                .fields()
+                .iter()
+                .map(|f| f.name().clone())
+                .collect();
+            sql = label_sql(&sql, &columns, column, table);
+        }
+    }
+
+    let df_batches = sink
+        .emit(
+            &format!("{}_dataframe", agg.name()),
+            &format!("{} (DataFrame API)", agg.name()),
+            df,
+        )
+        .await?;
+    if let Some(grid) = agg.grid(&df_batches)? {
+        sink.write(
+            &format!("{}_grid", agg.name()),
+            &format!("{} (grid)", agg.name()),
+            grid.schema(),
+            &[grid],
+        )?;
+    }
+
+    info!(
+        "\n==============================\n{} (SQL): {}\n==============================",
+        agg.name(),
+        agg.description()
+    );
+
+    let sql_df = input.ctx.sql(&sql).await?.sort(agg.sort_order())?;
+    let sql_batches = sink
+        .emit(
+            &format!("{}_sql", agg.name()),
+            &format!("{} (SQL)", agg.name()),
+            sql_df,
+        )
+        .await?;
+
+    let Some(tolerance) = tolerance else {
+        return Ok(None);
+    };
+    let approximate = agg.approximate_columns(input);
+    let title = match approximate.is_empty() {
+        true => agg.name().to_string(),
+        false => format!("{} ({} not compared)", agg.name(), approximate.join(", ")),
+    };
+    Ok(Some(compare_batches(
+        &title,
+        &without_columns(&df_batches, &approximate)?,
+        &without_columns(&sql_batches, &approximate)?,
+        tolerance,
+    )?))
+}
+
+/// `batches` without the `columns` that exist in them
+fn without_columns(batches: &[RecordBatch], columns: &[&str]) -> Result<Vec<RecordBatch>> {
+    batches
+        .iter()
+        .map(|batch| {
+            let schema = batch.schema();
+            let kept: Vec<usize> = schema
+                .fields()
+                .iter()
+                .enumerate()
+                .filter(|(_, f)| !columns.contains(&f.name().as_str()))
+                .map(|(i, _)| i)
+                .collect();
+            Ok(batch.project(&kept)?)
+        })
+        .collect()
+}
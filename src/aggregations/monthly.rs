@@ -0,0 +1,60 @@
+use super::{Aggregation, AggregationInput};
+use anyhow::Result;
+use datafusion::functions::datetime::expr_fn::date_trunc;
+use datafusion::functions_aggregate::expr_fn::{avg, count, sum};
+use datafusion::logical_expr::SortExpr;
+use datafusion::prelude::*;
+
+/// Trips and revenue by pickup month
+pub struct TripsByMonth;
+
+impl Aggregation for TripsByMonth {
+    fn name(&self) -> &'static str {
+        "trips_by_month"
+    }
+
+    fn description(&self) -> &'static str {
+        "Trips and revenue by pickup month"
+    }
+
+    fn dataframe(&self, input: &AggregationInput) -> Result<DataFrame> {
+        // pickup_month = date_trunc('month', pickup_datetime)
+        let pickup_month = input
+            .window
+            .bucket(date_trunc(lit("month"), col("pickup_datetime")))?
+            .alias("pickup_month");
+
+        Ok(input.trips.clone().aggregate(
+            vec![pickup_month],
+            vec![
+                count(lit(1)).alias("trip_count"),
+                sum(col("total_amount")).alias("total_revenue"),
+                avg(col("fare_amount")).alias("avg_fare"),
+            ],
+        )?)
+    }
+
+    fn sql(&self, input: &AggregationInput) -> String {
+        format!(
+            r#"
+            SELECT
+                {pickup_month} AS pickup_month,
+                COUNT(*) AS trip_count,
+                SUM(total_amount) AS total_revenue,
+                AVG(fare_amount) AS avg_fare
+            FROM {table}
+            {where_clause}
+            GROUP BY 1
+        "#,
+            pickup_month = input
+                .window
+                .bucket_sql("date_trunc('month', pickup_datetime)"),
+            table = input.table,
+            where_clause = input.where_sql(),
+        )
+    }
+
+    fn sort_order(&self) -> Vec<SortExpr> {
+        vec![col("pickup_month").sort(true, true)]
+    }
+}
@@ -0,0 +1,69 @@
+use super::{Aggregation, AggregationInput};
+use crate::codes::CodeTable;
+use anyhow::Result;
+use datafusion::functions_aggregate::expr_fn::{avg, count, sum};
+use datafusion::logical_expr::SortExpr;
+use datafusion::prelude::*;
+
+/// Tip behavior by payment type
+pub struct TipsByPaymentType;
+
+impl Aggregation for TipsByPaymentType {
+    fn name(&self) -> &'static str {
+        "tips_by_payment_type"
+    }
+
+    fn description(&self) -> &'static str {
+        "Tip behavior by payment type"
+    }
+
+    fn code_columns(&self) -> Vec<(&'static str, CodeTable)> {
+        vec![("payment_type", CodeTable::PaymentType)]
+    }
+
+    fn dataframe(&self, input: &AggregationInput) -> Result<DataFrame> {
+        // tip_rate = SUM(tip_amount) / SUM(total_amount)
+        // Step 1: aggregate sums separately
+        let base = input.trips.clone().aggregate(
+            vec![col("payment_type")],
+            vec![
+                count(lit(1)).alias("trip_count"),
+                avg(col("tip_amount")).alias("avg_tip_amount"),
+                sum(col("tip_amount")).alias("sum_tip_amount"),
+                sum(col("total_amount")).alias("sum_total_amount"),
+            ],
+        )?;
+
+        // Step 2: compute tip_rate in a projection and drop the helper columns
+        Ok(base.select(vec![
+            col("payment_type"),
+            col("trip_count"),
+            col("avg_tip_amount"),
+            (col("sum_tip_amount") / col("sum_total_amount")).alias("tip_rate"),
+        ])?)
+    }
+
+    fn sql(&self, input: &AggregationInput) -> String {
+        format!(
+            r#"
+            SELECT
+                payment_type,
+                COUNT(*) AS trip_count,
+                AVG(tip_amount) AS avg_tip_amount,
+                SUM(tip_amount) / SUM(total_amount) AS tip_rate
+            FROM {table}
+            {where_clause}
+            GROUP BY 1
+        "#,
+            table = input.table,
+            where_clause = input.where_sql(),
+        )
+    }
+
+    fn sort_order(&self) -> Vec<SortExpr> {
+        vec![
+            col("trip_count").sort(false, true),
+            col("payment_type").sort(true, true),
+        ]
+    }
+}
@@ -0,0 +1,150 @@
+use crate::export::{replace_dataset, ExportFormat};
+use crate::output::OutputSink;
+use crate::window::TimeWindow;
+use anyhow::Result;
+use clap::ValueEnum;
+use datafusion::functions_aggregate::expr_fn::count;
+use datafusion::prelude::*;
+use log::info;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Name of the column holding why a row was quarantined
+pub const REJECT_REASON: &str = "reject_reason";
+
+/// Rules a trip must pass to be kept by the `clean` command
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum CleanRule {
+    /// total_amount below zero (refunds, voids and corrections)
+    #[value(name = "negative_total")]
+    NegativeTotal,
+    /// dropoff_datetime earlier than pickup_datetime
+    #[value(name = "dropoff_before_pickup")]
+    DropoffBeforePickup,
+    /// Pickup outside the analyzed years (or --from/--to window)
+    #[value(name = "out_of_year")]
+    OutOfYear,
+    /// payment_type not in the TLC data dictionary (1-6)
+    #[value(name = "unknown_payment_type")]
+    UnknownPaymentType,
+}
+
+impl CleanRule {
+    pub fn name(self) -> &'static str {
+        match self {
+            CleanRule::NegativeTotal => "negative_total",
+            CleanRule::DropoffBeforePickup => "dropoff_before_pickup",
+            CleanRule::OutOfYear => "out_of_year",
+            CleanRule::UnknownPaymentType => "unknown_payment_type",
+        }
+    }
+
+    /// Rows matching the predicate are rejected
+    fn predicate(self, window: &TimeWindow) -> Expr {
+        match self {
+            CleanRule::NegativeTotal => col("total_amount").lt(lit(0.0)),
+            CleanRule::DropoffBeforePickup => col("dropoff_datetime").lt(col("pickup_datetime")),
+            CleanRule::OutOfYear => window.contains().is_not_true(),
+            CleanRule::UnknownPaymentType => col("payment_type")
+                .between(lit(1i64), lit(6i64))
+                .is_not_true(),
+        }
+    }
+}
+
+impl fmt::Display for CleanRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Where `clean` writes its datasets: `<dir>/trips` (accepted) and `<dir>/quarantine`
+#[derive(Clone, Debug)]
+pub struct CleanDirs {
+    pub trips: PathBuf,
+    pub quarantine: PathBuf,
+}
+
+impl CleanDirs {
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            trips: dir.join("trips"),
+            quarantine: dir.join("quarantine"),
+        }
+    }
+}
+
+/// `reject_reason`: comma-separated names of every failed rule, NULL for accepted rows
+fn reject_reason(rules: &[CleanRule], window: &TimeWindow) -> Result<Expr> {
+    let mut reasons = Vec::with_capacity(rules.len());
+    for rule in rules {
+        reasons.push(when(rule.predicate(window), lit(rule.name())).end()?);
+    }
+    // concat_ws skips NULLs, so a row failing no rule yields ''
+    Ok(nullif(concat_ws(lit(","), reasons), lit("")))
+}
+
+/// Split `trips` into accepted rows (written to `dirs.trips`) and rejected rows with a
+/// `reject_reason` column (written to `dirs.quarantine`), both as Parquet datasets.
+///
+/// Previous outputs in those two folders are replaced.
+pub async fn clean(
+    trips: DataFrame,
+    rules: &[CleanRule],
+    window: &TimeWindow,
+    dirs: &CleanDirs,
+    sink: &OutputSink,
+) -> Result<()> {
+    let columns: Vec<Expr> = trips
+        .schema()
+        .fields()
+        .iter()
+        .map(|f| ident(f.name()))
+        .collect();
+    let checked = trips.with_column(REJECT_REASON, reject_reason(rules, window)?)?;
+
+    let accepted = checked
+        .clone()
+        .filter(col(REJECT_REASON).is_null())?
+        .select(columns)?;
+    let rejected = checked.filter(col(REJECT_REASON).is_not_null())?;
+
+    let accepted_rows = replace_dataset(accepted, ExportFormat::Parquet, &dirs.trips).await?;
+    let rejected_rows =
+        replace_dataset(rejected.clone(), ExportFormat::Parquet, &dirs.quarantine).await?;
+
+    info!(
+        "Accepted {} rows -> {}\nQuarantined {} rows -> {}",
+        accepted_rows,
+        dirs.trips.display(),
+        rejected_rows,
+        dirs.quarantine.display()
+    );
+
+    // The quarantine can hold millions of rows: summarize the written files rather than
+    // keeping them in memory
+    let quarantine = match rejected_rows {
+        0 => rejected,
+        _ => {
+            let (state, _) = rejected.into_parts();
+            SessionContext::new_with_state(state)
+                .read_parquet(
+                    dirs.quarantine.to_string_lossy().as_ref(),
+                    ParquetReadOptions::default(),
+                )
+                .await?
+        }
+    };
+    let by_reason = quarantine
+        .aggregate(
+            vec![col(REJECT_REASON)],
+            vec![count(lit(1)).alias("rejected_rows")],
+        )?
+        .sort(vec![
+            col("rejected_rows").sort(false, true),
+            col(REJECT_REASON).sort(true, true),
+        ])?;
+    sink.emit("clean_rejects", "Rejected rows by reason", by_reason)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,156 @@
+//! Built-in TLC data dictionary tables for the coded trip columns.
+//!
+//! Each dictionary is registered as a `(code, label)` table, so SQL can join it directly
+//! (`LEFT JOIN payment_types pt ON t.payment_type = pt.code`); aggregations list their
+//! coded output columns in [`crate::aggregations::Aggregation::code_columns`] to get a
+//! `<column>_label` next to each code with `--labels`.
+
+use anyhow::Result;
+use datafusion::arrow::array::{ArrayRef, Int64Array, RecordBatch, StringArray};
+use datafusion::arrow::datatypes::{DataType, Field, Schema};
+use datafusion::prelude::*;
+use std::sync::Arc;
+
+/// Label of codes that are missing from a dictionary (NULL, 0 and undocumented values)
+pub const UNKNOWN_LABEL: &str = "Unknown/voided";
+
+/// One code dictionary of the TLC yellow trip data dictionary
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CodeTable {
+    PaymentType,
+    RateCode,
+    Vendor,
+    StoreAndFwdFlag,
+}
+
+impl CodeTable {
+    pub const ALL: [CodeTable; 4] = [
+        CodeTable::PaymentType,
+        CodeTable::RateCode,
+        CodeTable::Vendor,
+        CodeTable::StoreAndFwdFlag,
+    ];
+
+    /// Name of the registered dictionary table
+    pub fn table_name(self) -> &'static str {
+        match self {
+            CodeTable::PaymentType => "payment_types",
+            CodeTable::RateCode => "rate_codes",
+            CodeTable::Vendor => "vendors",
+            CodeTable::StoreAndFwdFlag => "store_and_fwd_flags",
+        }
+    }
+
+    /// The dictionary as a one-batch `(code, label)` table; 0 and codes that are not
+    /// listed fall into [`UNKNOWN_LABEL`]
+    fn batch(self) -> Result<RecordBatch> {
+        let numeric = |entries: &[(i64, &'static str)]| -> (ArrayRef, Vec<&'static str>) {
+            (
+                Arc::new(Int64Array::from_iter_values(entries.iter().map(|e| e.0))),
+                entries.iter().map(|e| e.1).collect(),
+            )
+        };
+
+        let (codes, labels) = match self {
+            CodeTable::PaymentType => numeric(&[
+                (0, UNKNOWN_LABEL),
+                (1, "Credit card"),
+                (2, "Cash"),
+                (3, "No charge"),
+                (4, "Dispute"),
+                (5, "Unknown"),
+                (6, "Voided trip"),
+            ]),
+            CodeTable::RateCode => numeric(&[
+                (1, "Standard rate"),
+                (2, "JFK"),
+                (3, "Newark"),
+                (4, "Nassau or Westchester"),
+                (5, "Negotiated fare"),
+                (6, "Group ride"),
+                (99, UNKNOWN_LABEL),
+            ]),
+            CodeTable::Vendor => numeric(&[
+                (1, "Creative Mobile Technologies"),
+                (2, "Curb Mobility"),
+                (6, "Myle Technologies"),
+                (7, "Helix"),
+            ]),
+            CodeTable::StoreAndFwdFlag => (
+                Arc::new(StringArray::from(vec!["Y", "N"])) as ArrayRef,
+                vec!["Store and forward trip", "Not a store and forward trip"],
+            ),
+        };
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("code", codes.data_type().clone(), false),
+            Field::new("label", DataType::Utf8, false),
+        ]));
+        Ok(RecordBatch::try_new(
+            schema,
+            vec![codes, Arc::new(StringArray::from(labels))],
+        )?)
+    }
+}
+
+/// Register every dictionary table in `ctx`
+pub fn register_code_tables(ctx: &SessionContext) -> Result<()> {
+    for table in CodeTable::ALL {
+        let df = ctx.read_batch(table.batch()?)?;
+        ctx.register_table(table.table_name(), df.into_view())?;
+    }
+    Ok(())
+}
+
+/// Add `<column>_label` right after `column` by joining its dictionary (DataFrame API)
+pub fn label_dataframe(
+    ctx: &SessionContext,
+    df: DataFrame,
+    column: &str,
+    table: CodeTable,
+) -> Result<DataFrame> {
+    let names: Vec<String> = df
+        .schema()
+        .fields()
+        .iter()
+        .map(|f| f.name().clone())
+        .collect();
+    let dictionary = ctx.read_batch(table.batch()?)?.select(vec![
+        col("code").alias("__code"),
+        col("label").alias("__label"),
+    ])?;
+
+    let joined = df.join(dictionary, JoinType::Left, &[column], &["__code"], None)?;
+    let mut projection = Vec::with_capacity(names.len() + 1);
+    for name in &names {
+        projection.push(ident(name));
+        if name == column {
+            projection.push(
+                coalesce(vec![col("__label"), lit(UNKNOWN_LABEL)])
+                    .alias(format!("{}_label", column)),
+            );
+        }
+    }
+    Ok(joined.select(projection)?)
+}
+
+/// SQL equivalent of [`label_dataframe`]: wraps `sql`, whose result has `columns`
+pub fn label_sql(sql: &str, columns: &[String], column: &str, table: CodeTable) -> String {
+    let mut projection = Vec::with_capacity(columns.len() + 1);
+    for name in columns {
+        projection.push(format!("r.\"{}\"", name));
+        if name == column {
+            projection.push(format!(
+                "COALESCE(d.label, '{}') AS \"{}_label\"",
+                UNKNOWN_LABEL, column
+            ));
+        }
+    }
+    format!(
+        "SELECT {} FROM ({}) r LEFT JOIN {} d ON r.\"{}\" = d.code",
+        projection.join(", "),
+        sql,
+        table.table_name(),
+        column
+    )
+}
@@ -0,0 +1,543 @@
+//! Run settings read from a TOML or YAML file given with `--config`.
+//!
+//! Every key is optional and command-line flags win over the file, so a shared file can
+//! describe a deployment and a flag can still change one value for a single run:
+//!
+//! ```toml
+//! [dataset]
+//! name = "yellow"
+//! data_root = "/data/tlc"
+//! years = [2024, 2025]
+//! file_pattern = "yellow_tripdata_{year}-{month}.parquet"
+//! table = "yellow"
+//!
+//! [aggregations]
+//! only = ["trips_by_month", "daily_series"]
+//! percentiles = "approx"
+//!
+//! [filters]
+//! from = 2024-03-01
+//! window = "isolate"
+//!
+//! [output]
+//! format = "parquet"
+//! dir = "./out"
+//!
+//! [session]
+//! target_partitions = 8
+//! options = { "datafusion.execution.parquet.pushdown_filters" = true }
+//! ```
+
+use crate::aggregations::{self, PercentileMode};
+use crate::clean::CleanDirs;
+use crate::dataset::{check_year, Dataset, FilePattern};
+use crate::output::{OutputFormat, OutputSink};
+use crate::parity::Tolerance;
+use crate::session::{check_table_name, SessionBuilder, DEFAULT_DATA_ROOT};
+use crate::window::{TimeWindow, WindowMode};
+use anyhow::{anyhow, Context, Result};
+use chrono::NaiveDate;
+use clap::ValueEnum;
+use datafusion::arrow::array::{ArrayRef, RecordBatch, StringArray};
+use datafusion::arrow::datatypes::{DataType, Field, Schema};
+use serde::{Deserialize, Deserializer};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Default absolute and relative tolerance of the parity check
+pub const DEFAULT_TOLERANCE: f64 = 1e-9;
+
+/// Contents of a `--config` file, merged with the command-line flags
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub dataset: DatasetConfig,
+    pub aggregations: AggregationsConfig,
+    pub filters: FiltersConfig,
+    pub output: OutputConfig,
+    pub session: SessionSettings,
+}
+
+/// `[dataset]`: which files are loaded and under which table name
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatasetConfig {
+    #[serde(deserialize_with = "value_enum")]
+    pub name: Option<Dataset>,
+    pub data_root: Option<PathBuf>,
+    pub data_dir: Option<PathBuf>,
+    pub years: Option<Vec<i32>>,
+    /// Monthly file names with `{year}` and `{month}` placeholders
+    pub file_pattern: Option<String>,
+    /// Name of the registered trip table
+    pub table: Option<String>,
+    pub zones: Option<PathBuf>,
+    pub cleaned: Option<bool>,
+    pub clean_dir: Option<PathBuf>,
+    /// Refuse to run when a file is incompatible with the data dictionary
+    pub strict_schema: Option<bool>,
+}
+
+/// `[aggregations]`: options of the `aggregate` command (`only`, `labels` and
+/// `percentiles` also apply to `profile` and `serve`)
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AggregationsConfig {
+    pub only: Vec<String>,
+    pub labels: Option<bool>,
+    #[serde(deserialize_with = "value_enum")]
+    pub percentiles: Option<PercentileMode>,
+    pub parity: Option<bool>,
+    pub abs_tol: Option<f64>,
+    pub rel_tol: Option<f64>,
+}
+
+/// `[filters]`: analyzed pickup period
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FiltersConfig {
+    #[serde(deserialize_with = "date")]
+    pub from: Option<NaiveDate>,
+    #[serde(deserialize_with = "date")]
+    pub to: Option<NaiveDate>,
+    #[serde(deserialize_with = "value_enum")]
+    pub window: Option<WindowMode>,
+}
+
+/// `[output]`: where results are written
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    #[serde(deserialize_with = "value_enum")]
+    pub format: Option<OutputFormat>,
+    pub dir: Option<PathBuf>,
+}
+
+/// `[session]`: DataFusion configuration of the `SessionContext`
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionSettings {
+    pub target_partitions: Option<usize>,
+    pub batch_size: Option<usize>,
+    /// Any other `datafusion.*` option, e.g. `datafusion.execution.parquet.pushdown_filters`
+    pub options: BTreeMap<String, OptionValue>,
+}
+
+/// Value of a DataFusion option; numbers and booleans need no quotes in the file
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum OptionValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+}
+
+impl fmt::Display for OptionValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OptionValue::Bool(v) => write!(f, "{}", v),
+            OptionValue::Int(v) => write!(f, "{}", v),
+            OptionValue::Float(v) => write!(f, "{}", v),
+            OptionValue::String(v) => f.write_str(v),
+        }
+    }
+}
+
+/// Parse enums with the same spelling as their command-line flag, e.g. `arrow-ipc`
+fn value_enum<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: ValueEnum,
+{
+    let Some(value) = Option::<String>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    T::from_str(&value, true).map(Some).map_err(|_| {
+        let allowed: Vec<String> = T::value_variants()
+            .iter()
+            .filter_map(|v| v.to_possible_value())
+            .map(|v| v.get_name().to_string())
+            .collect();
+        serde::de::Error::custom(format!(
+            "invalid value '{}', expected one of: {}",
+            value,
+            allowed.join(", ")
+        ))
+    })
+}
+
+/// Parse a `YYYY-MM-DD` date, quoted or not (TOML has a date type)
+fn date<'de, D>(deserializer: D) -> Result<Option<NaiveDate>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum DateValue {
+        Toml(toml::value::Datetime),
+        Text(String),
+    }
+
+    let text = match Option::<DateValue>::deserialize(deserializer)? {
+        None => return Ok(None),
+        Some(DateValue::Toml(date)) => date.to_string(),
+        Some(DateValue::Text(text)) => text,
+    };
+    NaiveDate::parse_from_str(&text, "%Y-%m-%d")
+        .map(Some)
+        .map_err(|_| {
+            serde::de::Error::custom(format!("invalid date '{}', expected YYYY-MM-DD", text))
+        })
+}
+
+impl Config {
+    /// Read a `.toml`, `.yaml` or `.yml` file
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Cannot read config file {}", path.display()))?;
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        match extension.as_str() {
+            "toml" => toml::from_str(&text).map_err(anyhow::Error::from),
+            "yaml" | "yml" => serde_yaml::from_str(&text).map_err(anyhow::Error::from),
+            _ => Err(anyhow!(
+                "Unknown config format, use a .toml, .yaml or .yml file"
+            )),
+        }
+        .with_context(|| format!("Invalid config file {}", path.display()))
+    }
+
+    pub fn dataset(&self) -> Dataset {
+        self.dataset.name.unwrap_or(Dataset::Yellow)
+    }
+
+    /// Analyzed years, sorted and de-duplicated like [`SessionBuilder::years`]
+    pub fn years(&self) -> Vec<i32> {
+        let mut years = self.dataset.years.clone().unwrap_or_else(|| vec![2025]);
+        years.sort_unstable();
+        years.dedup();
+        years
+    }
+
+    /// `clean_dir`, or `<data_root>/cleaned/<dataset>`
+    pub fn clean_dir(&self) -> PathBuf {
+        self.dataset.clean_dir.clone().unwrap_or_else(|| {
+            self.dataset
+                .data_root
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(DEFAULT_DATA_ROOT))
+                .join("cleaned")
+                .join(self.dataset().name())
+        })
+    }
+
+    /// `[session]` as DataFusion option keys and values
+    pub fn session_options(&self) -> HashMap<String, String> {
+        let session = &self.session;
+        let mut options: HashMap<String, String> = session
+            .options
+            .iter()
+            .map(|(key, value)| (key.clone(), value.to_string()))
+            .collect();
+        if let Some(n) = session.target_partitions {
+            options.insert(
+                "datafusion.execution.target_partitions".to_string(),
+                n.to_string(),
+            );
+        }
+        if let Some(n) = session.batch_size {
+            options.insert("datafusion.execution.batch_size".to_string(), n.to_string());
+        }
+        options
+    }
+
+    /// Session described by the settings
+    pub fn session_builder(&self) -> Result<SessionBuilder> {
+        let dataset = &self.dataset;
+        let filters = &self.filters;
+
+        let mut builder = SessionBuilder::new(self.dataset())
+            .years(self.years())
+            .window_mode(filters.window.unwrap_or(WindowMode::Drop));
+        if let Some(root) = &dataset.data_root {
+            builder = builder.data_root(root);
+        }
+        if let Some(dir) = &dataset.data_dir {
+            builder = builder.data_dir(dir);
+        }
+        if let Some(pattern) = &dataset.file_pattern {
+            builder = builder.file_pattern(FilePattern::new(pattern.as_str())?);
+        }
+        if let Some(table) = &dataset.table {
+            builder = builder.table(table);
+        }
+        if let Some(zones) = &dataset.zones {
+            builder = builder.zones(zones);
+        }
+        if dataset.cleaned.unwrap_or(false) {
+            builder = builder.cleaned(CleanDirs::new(&self.clean_dir()));
+        }
+        if let Some(strict) = dataset.strict_schema {
+            builder = builder.strict_schema(strict);
+        }
+        if let Some(from) = filters.from {
+            builder = builder.from(from);
+        }
+        if let Some(to) = filters.to {
+            builder = builder.to(to);
+        }
+        for (key, value) in self.session_options() {
+            builder = builder.option(key, value);
+        }
+        Ok(builder)
+    }
+
+    /// Output sink of `[output]`, printing tables to stdout by default
+    pub fn sink(&self) -> Result<OutputSink> {
+        OutputSink::new(
+            self.output.format.unwrap_or(OutputFormat::Table),
+            self.output.dir.clone(),
+        )
+    }
+}
+
+impl AggregationsConfig {
+    pub fn labels(&self) -> bool {
+        self.labels.unwrap_or(false)
+    }
+
+    pub fn percentiles(&self) -> PercentileMode {
+        self.percentiles.unwrap_or(PercentileMode::Exact)
+    }
+
+    /// Tolerance of the parity check, `None` when it is disabled
+    pub fn tolerance(&self) -> Option<Tolerance> {
+        self.parity.unwrap_or(true).then(|| Tolerance {
+            abs: self.abs_tol.unwrap_or(DEFAULT_TOLERANCE),
+            rel: self.rel_tol.unwrap_or(DEFAULT_TOLERANCE),
+        })
+    }
+}
+
+/// One row per check of the `config check` command: `check` and `status` (`ok`, with what
+/// was found, or the problem).
+///
+/// Checks the settings without loading any data: aggregation names, years and time window,
+/// file pattern, table name, DataFusion options, output, zone lookup and the data folders.
+/// Returns the report and the number of failed checks.
+pub fn check(config: &Config) -> Result<(RecordBatch, usize)> {
+    let years = config.years();
+    let mut checks: Vec<(&str, Result<String>)> = vec![
+        (
+            "aggregations",
+            aggregations::select(&config.aggregations.only)
+                .map(|selected| format!("{} selected", selected.len())),
+        ),
+        (
+            "years",
+            years
+                .iter()
+                .try_for_each(|&year| check_year(year))
+                .and_then(|()| match (years.first(), years.last()) {
+                    (Some(first), Some(last)) => TimeWindow::new(
+                        "pickup_datetime",
+                        *first,
+                        *last,
+                        config.filters.from,
+                        config.filters.to,
+                        config.filters.window.unwrap_or(WindowMode::Drop),
+                    )
+                    .map(|window| window.to_string()),
+                    _ => Err(anyhow!("No year to analyze")),
+                }),
+        ),
+        (
+            "file_pattern",
+            match &config.dataset.file_pattern {
+                Some(pattern) => FilePattern::new(pattern.as_str()).map(|p| p.to_string()),
+                None => Ok(config.dataset().file_pattern().to_string()),
+            },
+        ),
+        (
+            "table",
+            match config.dataset.table.as_deref() {
+                Some(table) => check_table_name(table).map(|()| table.to_string()),
+                None => Ok(config.dataset().table_name().to_string()),
+            },
+        ),
+        (
+            "session",
+            config
+                .session_options()
+                .into_iter()
+                .fold(
+                    SessionBuilder::new(config.dataset()),
+                    |builder, (key, value)| builder.option(key, value),
+                )
+                .session_config()
+                .map(|_| format!("{} option(s)", config.session_options().len())),
+        ),
+        (
+            "output",
+            match (config.output.format, &config.output.dir) {
+                (Some(format), None) if format.is_binary() => Err(anyhow!(
+                    "{} output needs a dir",
+                    format
+                        .to_possible_value()
+                        .map(|v| v.get_name().to_string())
+                        .unwrap_or_default()
+                )),
+                (_, Some(dir)) => Ok(dir.display().to_string()),
+                (_, None) => Ok("stdout".to_string()),
+            },
+        ),
+        (
+            "zones",
+            match &config.dataset.zones {
+                Some(path) if !path.is_file() => {
+                    Err(anyhow!("Zone lookup not found: {}", path.display()))
+                }
+                Some(path) => Ok(path.display().to_string()),
+                None => Ok("default".to_string()),
+            },
+        ),
+    ];
+
+    let data = match config.dataset.cleaned.unwrap_or(false) {
+        true => {
+            let dirs = CleanDirs::new(&config.clean_dir());
+            match dirs.trips.is_dir() {
+                true => Ok(dirs.trips.display().to_string()),
+                false => Err(anyhow!(
+                    "No cleaned dataset at {}, run the clean command first",
+                    dirs.trips.display()
+                )),
+            }
+        }
+        false => config
+            .session_builder()
+            .and_then(|builder| builder.data_files())
+            .map(|files| format!("{} monthly file(s)", files.len())),
+    };
+    checks.push(("data_files", data));
+
+    let failed = checks.iter().filter(|(_, status)| status.is_err()).count();
+    let (names, statuses): (Vec<&str>, Vec<String>) = checks
+        .into_iter()
+        .map(|(name, status)| match status {
+            Ok(found) => (name, format!("ok: {}", found)),
+            Err(e) => (name, format!("{:#}", e)),
+        })
+        .unzip();
+
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("check", DataType::Utf8, false),
+        Field::new("status", DataType::Utf8, false),
+    ]));
+    let batch = RecordBatch::try_new(
+        schema,
+        vec![
+            Arc::new(StringArray::from(names)) as ArrayRef,
+            Arc::new(StringArray::from(statuses)),
+        ],
+    )?;
+    Ok((batch, failed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn toml_config(text: &str) -> Result<Config> {
+        Ok(toml::from_str(text)?)
+    }
+
+    fn yaml_config(text: &str) -> Result<Config> {
+        Ok(serde_yaml::from_str(text)?)
+    }
+
+    fn date(s: &str) -> Option<NaiveDate> {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()
+    }
+
+    #[test]
+    fn parses_dates_quoted_or_not() {
+        let config = toml_config("[filters]\nfrom = 2024-03-01\nto = \"2024-03-31\"").unwrap();
+        assert_eq!(config.filters.from, date("2024-03-01"));
+        assert_eq!(config.filters.to, date("2024-03-31"));
+
+        let config = yaml_config("filters:\n  from: 2024-03-01\n  to: '2024-03-31'").unwrap();
+        assert_eq!(config.filters.from, date("2024-03-01"));
+        assert_eq!(config.filters.to, date("2024-03-31"));
+
+        assert!(toml_config("[filters]\nfrom = \"03/01/2024\"").is_err());
+        assert!(toml_config("[filters]\nfrom = 2024-03-01T10:00:00").is_err());
+        assert!(yaml_config("filters:\n  from: 2024-02-30").is_err());
+    }
+
+    #[test]
+    fn parses_enums_like_their_flags() {
+        let config = toml_config(
+            r#"
+            dataset = { name = "fhvhv" }
+            aggregations = { percentiles = "approx" }
+            filters = { window = "Isolate" }
+            output = { format = "arrow-ipc" }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.dataset(), Dataset::Fhvhv);
+        assert_eq!(config.aggregations.percentiles(), PercentileMode::Approx);
+        assert_eq!(config.filters.window, Some(WindowMode::Isolate));
+        assert_eq!(config.output.format, Some(OutputFormat::ArrowIpc));
+
+        let error = toml_config("output = { format = \"arrow_ipc\" }").unwrap_err();
+        assert!(error.to_string().contains("arrow-ipc"), "{}", error);
+        assert!(yaml_config("dataset:\n  name: limo").is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert!(toml_config("[dataset]\nyear = 2024").is_err());
+        assert!(toml_config("[outputs]\ndir = \"./out\"").is_err());
+        assert!(yaml_config("session:\n  partitions: 4").is_err());
+        assert!(toml_config("").is_ok());
+    }
+
+    #[test]
+    fn sorts_and_deduplicates_years() {
+        let config = toml_config("[dataset]\nyears = [2025, 2023, 2025, 2024]").unwrap();
+        assert_eq!(config.years(), vec![2023, 2024, 2025]);
+        assert_eq!(Config::default().years(), vec![2025]);
+    }
+
+    #[test]
+    fn maps_session_settings_to_datafusion_options() {
+        let config = toml_config(
+            r#"
+            [session]
+            target_partitions = 8
+            batch_size = 4096
+            options = { "datafusion.execution.parquet.pushdown_filters" = true, "datafusion.execution.time_zone" = "UTC" }
+            "#,
+        )
+        .unwrap();
+        let options = config.session_options();
+        let option = |key: &str| options.get(key).map(String::as_str);
+        assert_eq!(options.len(), 4);
+        assert_eq!(option("datafusion.execution.target_partitions"), Some("8"));
+        assert_eq!(option("datafusion.execution.batch_size"), Some("4096"));
+        assert_eq!(
+            option("datafusion.execution.parquet.pushdown_filters"),
+            Some("true")
+        );
+        assert_eq!(option("datafusion.execution.time_zone"), Some("UTC"));
+    }
+}
@@ -0,0 +1,231 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::{Datelike, Local};
+use clap::ValueEnum;
+use datafusion::arrow::datatypes::{DataType, TimeUnit};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// TLC trip record datasets
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Dataset {
+    /// Yellow taxi trips (`yellow_tripdata_*`, `tpep_*` columns)
+    Yellow,
+    /// Green taxi trips (`green_tripdata_*`, `lpep_*` columns)
+    Green,
+    /// For-hire vehicle trips (`fhv_tripdata_*`)
+    Fhv,
+    /// High-volume for-hire vehicle trips (`fhvhv_tripdata_*`, Uber/Lyft/...)
+    Fhvhv,
+}
+
+/// Where a canonical column comes from in a dataset's raw files
+pub(crate) enum Source {
+    Column(&'static str),
+    /// Sum of several raw columns (NULLs count as 0)
+    Sum(&'static [&'static str]),
+    /// Not recorded by this dataset
+    Null,
+}
+
+const TIMESTAMP: DataType = DataType::Timestamp(TimeUnit::Microsecond, None);
+
+/// Shared schema every dataset is mapped to, so the same aggregations run on all of them
+pub const CANONICAL_COLUMNS: &[(&str, DataType)] = &[
+    ("vendor_id", DataType::Int64),
+    ("request_datetime", TIMESTAMP),
+    ("pickup_datetime", TIMESTAMP),
+    ("dropoff_datetime", TIMESTAMP),
+    ("passenger_count", DataType::Int64),
+    ("trip_distance", DataType::Float64),
+    ("ratecode_id", DataType::Int64),
+    ("store_and_fwd_flag", DataType::Utf8),
+    ("pu_location_id", DataType::Int64),
+    ("do_location_id", DataType::Int64),
+    ("payment_type", DataType::Int64),
+    ("fare_amount", DataType::Float64),
+    ("extra", DataType::Float64),
+    ("mta_tax", DataType::Float64),
+    ("tip_amount", DataType::Float64),
+    ("tolls_amount", DataType::Float64),
+    ("improvement_surcharge", DataType::Float64),
+    ("total_amount", DataType::Float64),
+    ("congestion_surcharge", DataType::Float64),
+    ("airport_fee", DataType::Float64),
+    ("cbd_congestion_fee", DataType::Float64),
+];
+
+impl Dataset {
+    pub fn name(self) -> &'static str {
+        match self {
+            Dataset::Yellow => "yellow",
+            Dataset::Green => "green",
+            Dataset::Fhv => "fhv",
+            Dataset::Fhvhv => "fhvhv",
+        }
+    }
+
+    /// Default name of the canonical table registered in the `SessionContext`
+    pub fn table_name(self) -> &'static str {
+        self.name()
+    }
+
+    /// Default name of the table exposing the raw (unmapped) columns
+    pub fn raw_table_name(self) -> String {
+        format!("{}_raw", self.name())
+    }
+
+    /// TLC monthly file name pattern, e.g. `green_tripdata_{year}-{month}.parquet`
+    pub fn file_pattern(self) -> FilePattern {
+        FilePattern(format!(
+            "{}_tripdata_{{year}}-{{month}}.parquet",
+            self.name()
+        ))
+    }
+
+    /// TLC monthly file name, e.g. `green_tripdata_2025-01.parquet`
+    pub fn file_name(self, year: i32, month: u32) -> String {
+        self.file_pattern().file_name(year, month)
+    }
+
+    /// Raw source of a canonical column; names are matched case-insensitively at load time
+    pub(crate) fn source(self, column: &str) -> Source {
+        use Dataset::*;
+        use Source::*;
+
+        match (self, column) {
+            (Yellow | Green, "vendor_id") => Column("VendorID"),
+            (Fhvhv, "request_datetime") => Column("request_datetime"),
+            (Yellow, "pickup_datetime") => Column("tpep_pickup_datetime"),
+            (Green, "pickup_datetime") => Column("lpep_pickup_datetime"),
+            (Fhv | Fhvhv, "pickup_datetime") => Column("pickup_datetime"),
+            (Yellow, "dropoff_datetime") => Column("tpep_dropoff_datetime"),
+            (Green, "dropoff_datetime") => Column("lpep_dropoff_datetime"),
+            (Fhv, "dropoff_datetime") => Column("dropOff_datetime"),
+            (Fhvhv, "dropoff_datetime") => Column("dropoff_datetime"),
+            (Yellow | Green, "passenger_count") => Column("passenger_count"),
+            (Yellow | Green, "trip_distance") => Column("trip_distance"),
+            (Fhvhv, "trip_distance") => Column("trip_miles"),
+            (Yellow | Green, "ratecode_id") => Column("RatecodeID"),
+            (Yellow | Green, "store_and_fwd_flag") => Column("store_and_fwd_flag"),
+            (Yellow | Green | Fhvhv, "pu_location_id") => Column("PULocationID"),
+            (Fhv, "pu_location_id") => Column("PUlocationID"),
+            (Yellow | Green | Fhvhv, "do_location_id") => Column("DOLocationID"),
+            (Fhv, "do_location_id") => Column("DOlocationID"),
+            (Yellow | Green, "payment_type") => Column("payment_type"),
+            (Yellow | Green, "fare_amount") => Column("fare_amount"),
+            (Fhvhv, "fare_amount") => Column("base_passenger_fare"),
+            (Yellow | Green, "extra") => Column("extra"),
+            (Yellow | Green, "mta_tax") => Column("mta_tax"),
+            (Yellow | Green, "tip_amount") => Column("tip_amount"),
+            (Fhvhv, "tip_amount") => Column("tips"),
+            (Yellow | Green, "tolls_amount") => Column("tolls_amount"),
+            (Fhvhv, "tolls_amount") => Column("tolls"),
+            (Yellow | Green, "improvement_surcharge") => Column("improvement_surcharge"),
+            (Yellow | Green, "total_amount") => Column("total_amount"),
+            // HVFHS records have no total, rebuild it from the rider-paid components
+            (Fhvhv, "total_amount") => Sum(&[
+                "base_passenger_fare",
+                "tolls",
+                "bcf",
+                "sales_tax",
+                "congestion_surcharge",
+                "cbd_congestion_fee",
+                "airport_fee",
+                "tips",
+            ]),
+            (Yellow | Green | Fhvhv, "congestion_surcharge") => Column("congestion_surcharge"),
+            (Yellow, "airport_fee") => Column("Airport_fee"),
+            (Fhvhv, "airport_fee") => Column("airport_fee"),
+            // Congestion relief zone toll, only present from 2025 on
+            (Yellow | Green | Fhvhv, "cbd_congestion_fee") => Column("cbd_congestion_fee"),
+            _ => Null,
+        }
+    }
+
+    /// Monthly files of `year` for this dataset found in `data_dir`, sorted by name
+    pub fn find_files(self, data_dir: &Path, year: i32) -> Result<Vec<PathBuf>> {
+        self.file_pattern().find_files(data_dir, year)
+    }
+}
+
+/// First year of published TLC trip records
+pub const FIRST_YEAR: i32 = 2009;
+
+/// Reject a year without TLC trip records: before 2009 or after the current year
+pub fn check_year(year: i32) -> Result<()> {
+    let current = Local::now().year();
+    if !(FIRST_YEAR..=current).contains(&year) {
+        return Err(anyhow!(
+            "TLC trip records cover {}..={}, got {}",
+            FIRST_YEAR,
+            current,
+            year
+        ));
+    }
+    Ok(())
+}
+
+/// Monthly file name with `{year}` and `{month}` (two digits) placeholders, for folders that
+/// do not follow the TLC naming, e.g. `trips_{year}_{month}.parquet`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FilePattern(String);
+
+impl FilePattern {
+    pub fn new(pattern: impl Into<String>) -> Result<Self> {
+        let pattern = pattern.into();
+        for placeholder in ["{year}", "{month}"] {
+            if pattern.matches(placeholder).count() != 1 {
+                return Err(anyhow!(
+                    "File pattern '{}' needs exactly one {} placeholder",
+                    pattern,
+                    placeholder
+                ));
+            }
+        }
+        if pattern.contains(['/', '\\']) {
+            return Err(anyhow!(
+                "File pattern '{}' is a file name, set the folder with data_root or data_dir",
+                pattern
+            ));
+        }
+        Ok(Self(pattern))
+    }
+
+    pub fn file_name(&self, year: i32, month: u32) -> String {
+        self.0
+            .replace("{year}", &year.to_string())
+            .replace("{month}", &format!("{:02}", month))
+    }
+
+    /// Files of `year` matching the pattern found in `data_dir`, sorted by name
+    pub fn find_files(&self, data_dir: &Path, year: i32) -> Result<Vec<PathBuf>> {
+        let names: Vec<String> = (1..=12).map(|m| self.file_name(year, m)).collect();
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(data_dir)
+            .with_context(|| format!("Cannot read data directory {}", data_dir.display()))?
+        {
+            let path = entry?.path();
+            let is_match = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| names.iter().any(|name| name == n));
+            if is_match {
+                files.push(path);
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+}
+
+impl fmt::Display for FilePattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl fmt::Display for Dataset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
@@ -0,0 +1,235 @@
+//! Expected raw schema of the TLC monthly files (the yellow taxi data dictionary) and the
+//! drift of a file's Parquet footer from it.
+
+use crate::dataset::Dataset;
+use crate::schema::{resolve, same_logical_type};
+use datafusion::arrow::datatypes::{DataType, Schema, TimeUnit};
+
+/// One column of the TLC data dictionary
+pub struct ExpectedColumn {
+    /// Raw column name, matched case-insensitively like when the table is registered
+    pub name: &'static str,
+    /// Type written by the current TLC files
+    pub data_type: DataType,
+    /// Without it the trip table cannot be built; other columns become NULL when missing
+    pub required: bool,
+}
+
+const fn column(name: &'static str, data_type: DataType, required: bool) -> ExpectedColumn {
+    ExpectedColumn {
+        name,
+        data_type,
+        required,
+    }
+}
+
+const TIMESTAMP: DataType = DataType::Timestamp(TimeUnit::Microsecond, None);
+
+/// Yellow taxi trip records as published since 2025
+pub const YELLOW_SCHEMA: &[ExpectedColumn] = &[
+    column("VendorID", DataType::Int32, true),
+    column("tpep_pickup_datetime", TIMESTAMP, true),
+    column("tpep_dropoff_datetime", TIMESTAMP, true),
+    column("passenger_count", DataType::Int64, false),
+    column("trip_distance", DataType::Float64, true),
+    column("RatecodeID", DataType::Int64, false),
+    column("store_and_fwd_flag", DataType::Utf8, false),
+    column("PULocationID", DataType::Int32, true),
+    column("DOLocationID", DataType::Int32, true),
+    column("payment_type", DataType::Int64, true),
+    column("fare_amount", DataType::Float64, true),
+    column("extra", DataType::Float64, false),
+    column("mta_tax", DataType::Float64, false),
+    column("tip_amount", DataType::Float64, true),
+    column("tolls_amount", DataType::Float64, false),
+    column("improvement_surcharge", DataType::Float64, false),
+    column("total_amount", DataType::Float64, true),
+    column("congestion_surcharge", DataType::Float64, false),
+    column("Airport_fee", DataType::Float64, false),
+    // Congestion relief zone toll, only present from 2025 on
+    column("cbd_congestion_fee", DataType::Float64, false),
+];
+
+/// Data dictionary of `dataset`, only yellow taxi records have one so far
+pub fn expected_schema(dataset: Dataset) -> Option<&'static [ExpectedColumn]> {
+    match dataset {
+        Dataset::Yellow => Some(YELLOW_SCHEMA),
+        Dataset::Green | Dataset::Fhv | Dataset::Fhvhv => None,
+    }
+}
+
+/// Coarse type family: a column changing type within its family can still be cast to the
+/// canonical schema, one changing family (e.g. a number stored as text) cannot
+#[derive(PartialEq, Eq)]
+enum Family {
+    Number,
+    Timestamp,
+    Text,
+    Other,
+}
+
+fn family(data_type: &DataType) -> Family {
+    match data_type {
+        t if t.is_numeric() => Family::Number,
+        DataType::Timestamp(_, _) => Family::Timestamp,
+        DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View => Family::Text,
+        _ => Family::Other,
+    }
+}
+
+/// How one file's columns differ from the data dictionary
+#[derive(Debug, Default)]
+pub struct SchemaDrift {
+    /// Dictionary columns absent from the file
+    pub missing: Vec<String>,
+    /// File columns absent from the dictionary
+    pub extra: Vec<String>,
+    /// Columns of another type, as `name: actual (expected expected)`
+    pub retyped: Vec<String>,
+    /// Why the file cannot be mapped to the trip table: missing required columns and
+    /// columns whose type changed family
+    pub incompatible: Vec<String>,
+}
+
+impl SchemaDrift {
+    /// Compare a file's footer schema with `expected`
+    pub fn new(expected: &[ExpectedColumn], schema: &Schema) -> Self {
+        let mut drift = SchemaDrift::default();
+
+        for column in expected {
+            match resolve(schema, column.name) {
+                None => {
+                    drift.missing.push(column.name.to_string());
+                    if column.required {
+                        drift
+                            .incompatible
+                            .push(format!("required column {} is missing", column.name));
+                    }
+                }
+                Some(field) if !same_logical_type(field.data_type(), &column.data_type) => {
+                    drift.retyped.push(format!(
+                        "{}: {} (expected {})",
+                        column.name,
+                        field.data_type(),
+                        column.data_type
+                    ));
+                    if family(field.data_type()) != family(&column.data_type) {
+                        drift.incompatible.push(format!(
+                            "{} is {}, expected {}",
+                            column.name,
+                            field.data_type(),
+                            column.data_type
+                        ));
+                    }
+                }
+                Some(_) => {}
+            }
+        }
+
+        drift.extra = schema
+            .fields()
+            .iter()
+            .map(|f| f.name())
+            .filter(|name| {
+                !expected
+                    .iter()
+                    .any(|column| column.name.eq_ignore_ascii_case(name))
+            })
+            .cloned()
+            .collect();
+        drift
+    }
+
+    /// Whether the file matches the dictionary exactly
+    pub fn is_empty(&self) -> bool {
+        self.missing.is_empty() && self.extra.is_empty() && self.retyped.is_empty()
+    }
+
+    pub fn is_compatible(&self) -> bool {
+        self.incompatible.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::datatypes::Field;
+
+    /// The yellow dictionary with `change` applied to each column (None drops it)
+    fn yellow(change: impl Fn(&ExpectedColumn) -> Option<Field>) -> Schema {
+        Schema::new(YELLOW_SCHEMA.iter().filter_map(change).collect::<Vec<_>>())
+    }
+
+    fn same(column: &ExpectedColumn) -> Option<Field> {
+        Some(Field::new(column.name, column.data_type.clone(), true))
+    }
+
+    #[test]
+    fn dictionary_schema_has_no_drift() {
+        let drift = SchemaDrift::new(YELLOW_SCHEMA, &yellow(same));
+        assert!(drift.is_empty(), "{:?}", drift);
+        assert!(drift.is_compatible());
+    }
+
+    #[test]
+    fn names_match_case_insensitively_and_string_views_are_strings() {
+        let schema = yellow(|c| match c.name {
+            "Airport_fee" => Some(Field::new("airport_fee", DataType::Float64, true)),
+            "store_and_fwd_flag" => Some(Field::new(c.name, DataType::Utf8View, true)),
+            _ => same(c),
+        });
+        assert!(SchemaDrift::new(YELLOW_SCHEMA, &schema).is_empty());
+    }
+
+    #[test]
+    fn older_vintages_drift_but_stay_compatible() {
+        let schema = yellow(|c| match c.name {
+            "cbd_congestion_fee" => None,
+            "passenger_count" => Some(Field::new(c.name, DataType::Float64, true)),
+            "tpep_pickup_datetime" => Some(Field::new(
+                c.name,
+                DataType::Timestamp(TimeUnit::Nanosecond, None),
+                true,
+            )),
+            _ => same(c),
+        });
+        let drift = SchemaDrift::new(YELLOW_SCHEMA, &schema);
+        assert_eq!(drift.missing, ["cbd_congestion_fee"]);
+        assert!(drift.extra.is_empty());
+        assert_eq!(
+            drift.retyped,
+            [
+                "tpep_pickup_datetime: Timestamp(ns) (expected Timestamp(µs))",
+                "passenger_count: Float64 (expected Int64)",
+            ]
+        );
+        assert!(drift.is_compatible());
+    }
+
+    #[test]
+    fn extra_columns_are_listed() {
+        let mut fields: Vec<Field> = YELLOW_SCHEMA.iter().filter_map(same).collect();
+        fields.push(Field::new("__index_level_0__", DataType::Int64, true));
+        let drift = SchemaDrift::new(YELLOW_SCHEMA, &Schema::new(fields));
+        assert_eq!(drift.extra, ["__index_level_0__"]);
+        assert!(drift.is_compatible());
+    }
+
+    #[test]
+    fn missing_required_columns_and_family_changes_are_incompatible() {
+        let schema = yellow(|c| match c.name {
+            "PULocationID" => None,
+            "fare_amount" => Some(Field::new(c.name, DataType::Utf8, true)),
+            _ => same(c),
+        });
+        let drift = SchemaDrift::new(YELLOW_SCHEMA, &schema);
+        assert_eq!(
+            drift.incompatible,
+            [
+                "required column PULocationID is missing",
+                "fare_amount is Utf8, expected Float64",
+            ]
+        );
+        assert!(!drift.is_compatible());
+    }
+}
@@ -0,0 +1,139 @@
+//! Streaming export of a table or query to a folder of Parquet, CSV or JSON part files.
+
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use datafusion::arrow::array::UInt64Array;
+use datafusion::dataframe::DataFrameWriteOptions;
+use datafusion::prelude::*;
+use std::path::Path;
+
+/// File format of an exported dataset
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    Parquet,
+    Csv,
+    /// Newline-delimited JSON
+    Json,
+}
+
+impl ExportFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Parquet => "parquet",
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+        }
+    }
+}
+
+/// Write a dataset of `df` in `format` to `dir`, returning the number of rows written.
+///
+/// An existing non-empty `dir` is refused unless `overwrite` is set; then only the part
+/// files of an earlier export in the same format (`<write id>_<n>.<ext>`) are deleted,
+/// everything else in the folder is kept.
+///
+/// Unlike [`crate::output::OutputSink`], the rows are streamed to part files and never
+/// collected in memory, so whole trip tables can be exported.
+pub async fn write_dataset(
+    df: DataFrame,
+    format: ExportFormat,
+    dir: &Path,
+    overwrite: bool,
+) -> Result<u64> {
+    if dir.exists() {
+        let entries = std::fs::read_dir(dir)
+            .with_context(|| format!("Cannot read {}", dir.display()))?
+            .collect::<std::io::Result<Vec<_>>>()?;
+        if !entries.is_empty() && !overwrite {
+            return Err(anyhow!(
+                "{} is not empty, pass --overwrite to replace the {} part files of an earlier export",
+                dir.display(),
+                format.extension()
+            ));
+        }
+        for entry in entries {
+            let path = entry.path();
+            if entry.file_type()?.is_file() && is_part_file(&path, format) {
+                std::fs::remove_file(&path)
+                    .with_context(|| format!("Cannot delete {}", path.display()))?;
+            }
+        }
+    }
+    write_parts(df, format, dir).await
+}
+
+/// Delete `dir` and write a dataset of `df` in `format` to it, for folders owned by this
+/// crate such as the outputs of [`crate::clean::clean`]
+pub(crate) async fn replace_dataset(
+    df: DataFrame,
+    format: ExportFormat,
+    dir: &Path,
+) -> Result<u64> {
+    if dir.exists() {
+        std::fs::remove_dir_all(dir)
+            .with_context(|| format!("Cannot replace {}", dir.display()))?;
+    }
+    write_parts(df, format, dir).await
+}
+
+/// Whether `path` is named like the part files DataFusion writes: a 16 character
+/// alphanumeric write id, `_`, the part number and the extension of `format`
+fn is_part_file(path: &Path, format: ExportFormat) -> bool {
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return false;
+    };
+    let Some(stem) = name.strip_suffix(&format!(".{}", format.extension())) else {
+        return false;
+    };
+    match stem.split_once('_') {
+        Some((id, part)) => {
+            id.len() == 16
+                && id.chars().all(|c| c.is_ascii_alphanumeric())
+                && !part.is_empty()
+                && part.chars().all(|c| c.is_ascii_digit())
+        }
+        None => false,
+    }
+}
+
+async fn write_parts(df: DataFrame, format: ExportFormat, dir: &Path) -> Result<u64> {
+    std::fs::create_dir_all(dir).with_context(|| format!("Cannot create {}", dir.display()))?;
+
+    // A trailing slash makes DataFusion write part files into the folder
+    let path = format!("{}/", dir.display());
+    let options = DataFrameWriteOptions::new();
+    let result = match format {
+        ExportFormat::Parquet => df.write_parquet(&path, options, None).await,
+        ExportFormat::Csv => df.write_csv(&path, options, None).await,
+        ExportFormat::Json => df.write_json(&path, options, None).await,
+    }
+    .with_context(|| format!("Cannot write {}", dir.display()))?;
+
+    // write_* return a single `count` row
+    let rows = result
+        .first()
+        .and_then(|b| b.column(0).as_any().downcast_ref::<UInt64Array>())
+        .map(|c| c.value(0))
+        .unwrap_or(0);
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_only_datafusion_part_files() {
+        let part = |name: &str, format| is_part_file(Path::new(name), format);
+        assert!(part("9wBtOXOSY5t4goX4_0.parquet", ExportFormat::Parquet));
+        assert!(part("/out/qqqB6SjJfubHeVmY_12.csv", ExportFormat::Csv));
+        assert!(!part("9wBtOXOSY5t4goX4_0.parquet", ExportFormat::Json));
+        assert!(!part(
+            "yellow_tripdata_2025-01.parquet",
+            ExportFormat::Parquet
+        ));
+        assert!(!part("taxi_zone_lookup.csv", ExportFormat::Csv));
+        assert!(!part("9wBtOXOSY5t4goX4_.parquet", ExportFormat::Parquet));
+        assert!(!part("9wBtOXOSY5t4goX4.parquet", ExportFormat::Parquet));
+    }
+}
@@ -0,0 +1,149 @@
+//! Listing of the `inventory` command: what each monthly file holds, read from its Parquet
+//! footer (row groups, sizes, column statistics) without scanning any data page.
+
+use crate::dataset::{Dataset, Source};
+use crate::schema::resolve;
+use crate::session::SessionBuilder;
+use anyhow::{Context, Result};
+use datafusion::arrow::array::{
+    Array, ArrayRef, Int64Array, RecordBatch, StringArray, TimestampMicrosecondArray,
+};
+use datafusion::arrow::compute::{cast, max, min};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use datafusion::parquet::arrow::arrow_reader::statistics::StatisticsConverter;
+use datafusion::parquet::arrow::arrow_reader::{ArrowReaderMetadata, ArrowReaderOptions};
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Footer summary of one monthly file
+struct FileInventory {
+    rows: i64,
+    row_groups: i64,
+    compressed_bytes: i64,
+    uncompressed_bytes: i64,
+    /// Earliest and latest pickup according to the row group statistics (µs since epoch)
+    pickup_min: Option<i64>,
+    pickup_max: Option<i64>,
+    created_by: Option<String>,
+}
+
+/// Smallest and largest value of a statistics array, as microseconds
+fn timestamp_bounds(mins: &ArrayRef, maxes: &ArrayRef) -> Result<(Option<i64>, Option<i64>)> {
+    let micros = DataType::Timestamp(TimeUnit::Microsecond, None);
+    let as_micros = |array: &ArrayRef| -> Result<TimestampMicrosecondArray> {
+        Ok(cast(array, &micros)?
+            .as_any()
+            .downcast_ref::<TimestampMicrosecondArray>()
+            .expect("cast to Timestamp(µs)")
+            .clone())
+    };
+    Ok((min(&as_micros(mins)?), max(&as_micros(maxes)?)))
+}
+
+fn inspect(path: &Path, dataset: Dataset) -> Result<FileInventory> {
+    let file = File::open(path).with_context(|| format!("Cannot open {}", path.display()))?;
+    let reader = ArrowReaderMetadata::load(&file, ArrowReaderOptions::default())
+        .with_context(|| format!("Cannot read Parquet footer of {}", path.display()))?;
+    let metadata = reader.metadata();
+    let row_groups = metadata.row_groups();
+
+    let (pickup_min, pickup_max) = match dataset.source("pickup_datetime") {
+        Source::Column(raw) => match resolve(reader.schema(), raw) {
+            Some(field) if matches!(field.data_type(), DataType::Timestamp(_, _)) => {
+                let converter = StatisticsConverter::try_new(
+                    field.name(),
+                    reader.schema(),
+                    reader.parquet_schema(),
+                )?;
+                timestamp_bounds(
+                    &converter.row_group_mins(row_groups)?,
+                    &converter.row_group_maxes(row_groups)?,
+                )?
+            }
+            _ => (None, None),
+        },
+        _ => (None, None),
+    };
+
+    Ok(FileInventory {
+        rows: metadata.file_metadata().num_rows(),
+        row_groups: row_groups.len() as i64,
+        compressed_bytes: row_groups.iter().map(|rg| rg.compressed_size()).sum(),
+        uncompressed_bytes: row_groups.iter().map(|rg| rg.total_byte_size()).sum(),
+        pickup_min,
+        pickup_max,
+        created_by: metadata.file_metadata().created_by().map(str::to_string),
+    })
+}
+
+/// One row per monthly file: `file`, `rows`, `row_groups`, `compressed_bytes` and
+/// `uncompressed_bytes` of the column chunks, `pickup_min`/`pickup_max` from the row group
+/// statistics (NULL when the writer stored none), `created_by` and `status` (`ok` or the
+/// error reading the footer).
+///
+/// A month whose rows are far below its neighbours, or whose pickups stop before the end
+/// of the month, was probably uploaded partially.
+pub fn inventory(builder: &SessionBuilder) -> Result<RecordBatch> {
+    let files = builder.data_files()?;
+
+    let mut names = Vec::new();
+    let mut statuses = Vec::new();
+    let mut found = Vec::new();
+    for path in &files {
+        names.push(path.display().to_string());
+        match inspect(path, builder.dataset()) {
+            Ok(file) => {
+                found.push(Some(file));
+                statuses.push("ok".to_string());
+            }
+            Err(e) => {
+                found.push(None);
+                statuses.push(format!("{:#}", e));
+            }
+        }
+    }
+
+    let int_column = |value: fn(&FileInventory) -> i64| -> ArrayRef {
+        Arc::new(Int64Array::from_iter(
+            found.iter().map(|f| f.as_ref().map(value)),
+        ))
+    };
+    let pickup_column = |value: fn(&FileInventory) -> Option<i64>| -> ArrayRef {
+        Arc::new(TimestampMicrosecondArray::from_iter(
+            found.iter().map(|f| f.as_ref().and_then(value)),
+        ))
+    };
+
+    let timestamp = DataType::Timestamp(TimeUnit::Microsecond, None);
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("file", DataType::Utf8, false),
+        Field::new("rows", DataType::Int64, true),
+        Field::new("row_groups", DataType::Int64, true),
+        Field::new("compressed_bytes", DataType::Int64, true),
+        Field::new("uncompressed_bytes", DataType::Int64, true),
+        Field::new("pickup_min", timestamp.clone(), true),
+        Field::new("pickup_max", timestamp, true),
+        Field::new("created_by", DataType::Utf8, true),
+        Field::new("status", DataType::Utf8, false),
+    ]));
+    let batch = RecordBatch::try_new(
+        schema,
+        vec![
+            Arc::new(StringArray::from(names)) as ArrayRef,
+            int_column(|f| f.rows),
+            int_column(|f| f.row_groups),
+            int_column(|f| f.compressed_bytes),
+            int_column(|f| f.uncompressed_bytes),
+            pickup_column(|f| f.pickup_min),
+            pickup_column(|f| f.pickup_max),
+            Arc::new(StringArray::from_iter(
+                found
+                    .iter()
+                    .map(|f| f.as_ref().and_then(|f| f.created_by.clone())),
+            )),
+            Arc::new(StringArray::from(statuses)),
+        ],
+    )?;
+    Ok(batch)
+}
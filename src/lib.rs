@@ -0,0 +1,33 @@
+//! NYC TLC trip record analytics with DataFusion.
+//!
+//! The `nyc_tlc_datafusion` binary is a thin command-line layer over this library:
+//! - [`session::SessionBuilder`] validates the TLC folders and registers the trip table
+//!   (plus code dictionaries, calendar and zone lookup) in a `SessionContext`
+//! - [`aggregations`] are typed analyses, each returning a `DataFrame` (and its SQL twin)
+//! - [`output::OutputSink`] writes results as a table, CSV, JSON, Parquet, Arrow IPC or Markdown
+//! - [`dictionary`] holds the TLC data dictionary and the schema drift of a file
+//! - [`config::Config`] describes a run in a TOML or YAML file and builds the session and sink
+//! - [`quality`], [`clean`], [`od_matrix`], [`sql_files`], [`validate`], [`inventory`],
+//!   [`export`], [`profile`] and [`serve`] back the other commands
+
+pub mod aggregations;
+pub mod clean;
+pub mod codes;
+pub mod config;
+pub mod dataset;
+pub mod dictionary;
+pub mod export;
+pub mod inventory;
+pub mod od_matrix;
+pub mod output;
+pub mod parity;
+pub mod profile;
+pub mod quality;
+pub mod repl;
+pub mod schema;
+pub mod serve;
+pub mod session;
+pub mod sql_files;
+pub mod validate;
+pub mod window;
+pub mod zones;
@@ -1,189 +1,765 @@
-use anyhow::{anyhow, Context, Result};
-use clap::Parser;
-use datafusion::arrow::util::pretty::pretty_format_batches;
-use datafusion::prelude::*;
-use datafusion::functions_aggregate::expr_fn::{avg, sum, count};
-use datafusion::functions::datetime::expr_fn::date_trunc;
+use anyhow::{anyhow, Result};
+use chrono::NaiveDate;
+use clap::{Parser, Subcommand};
+use datafusion::arrow::array::{RecordBatch, StringArray};
+use datafusion::arrow::datatypes::{DataType, Field, Schema};
+use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use nyc_tlc_datafusion::aggregations::{self, run_aggregation, PercentileMode};
+use nyc_tlc_datafusion::clean::{self, CleanDirs, CleanRule};
+use nyc_tlc_datafusion::config::{self, AggregationsConfig, Config};
+use nyc_tlc_datafusion::dataset::{check_year, Dataset};
+use nyc_tlc_datafusion::export::{self, ExportFormat};
+use nyc_tlc_datafusion::od_matrix::{self, Hours, OdFilter, OdValue};
+use nyc_tlc_datafusion::output::{OutputFormat, OutputSink};
+use nyc_tlc_datafusion::session::TlcSession;
+use nyc_tlc_datafusion::sql_files::{self, QueryParams};
+use nyc_tlc_datafusion::window::WindowMode;
+use nyc_tlc_datafusion::{inventory, profile, quality, repl, schema, serve, validate};
 
 #[derive(Parser, Debug)]
 #[command(
     name = "nyc_tlc_datafusion",
-    about = "NYC TLC Yellow Taxi 2025 analytics using DataFusion DataFrame API + SQL"
+    about = "NYC TLC trip record analytics using DataFusion DataFrame API + SQL"
 )]
 struct Args {
-    /// Folder containing the 2025 yellow parquet files (12 files, one per month)
-    /// Example: ./data/yellow/2025
-    #[arg(long, default_value = "./data/yellow/2025")]
-    data_dir: String,
-
-    /// Year to analyze (mostly informational / validation)
-    #[arg(long, default_value_t = 2025)]
-    year: i32,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// TOML or YAML file with the settings of this run; flags given here override it
+    /// Example: --config configs/prod.toml
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// TLC dataset to analyze [default: yellow]
+    #[arg(long, global = true, value_enum)]
+    dataset: Option<Dataset>,
+
+    /// Root folder holding one <dataset>/<year> sub-folder per year [default: ./data]
+    /// Example: ./data -> ./data/yellow/2024, ./data/yellow/2025
+    #[arg(long, global = true)]
+    data_root: Option<PathBuf>,
+
+    /// Single folder containing the monthly parquet files of every analyzed year
+    /// (overrides --data-root), e.g. ./data/yellow/2025
+    #[arg(long, global = true)]
+    data_dir: Option<PathBuf>,
+
+    /// Year to analyze (file validation and default time window) [default: 2025]
+    #[arg(long, global = true, value_parser = parse_year)]
+    year: Option<i32>,
+
+    /// Several years to analyze as one table (overrides --year)
+    /// Examples: 2019..=2025, 2019..2025, 2023,2025
+    #[arg(long, global = true, value_parser = parse_years)]
+    years: Option<Years>,
+
+    /// First pickup date to include (inclusive), defaults to Jan 1 of the first year
+    /// Example: 2025-03-01
+    #[arg(long, global = true)]
+    from: Option<NaiveDate>,
+
+    /// Last pickup date to include (inclusive), defaults to Dec 31 of the last year
+    #[arg(long, global = true)]
+    to: Option<NaiveDate>,
+
+    /// TLC taxi zone lookup CSV used for borough/zone aggregations
+    /// Defaults to <data-root>/taxi_zone_lookup.csv (zone aggregations are skipped if absent)
+    #[arg(long, global = true)]
+    zones: Option<PathBuf>,
+
+    /// Analyze the cleaned dataset written by the `clean` command instead of the raw files
+    #[arg(long, global = true)]
+    cleaned: bool,
+
+    /// Folder of the cleaned and quarantine datasets, defaults to <data-root>/cleaned/<dataset>
+    #[arg(long, global = true)]
+    clean_dir: Option<PathBuf>,
+
+    /// Refuse to run when a file's footer is incompatible with the TLC data dictionary
+    /// (missing required columns, changed types) instead of only warning
+    #[arg(long, global = true)]
+    strict_schema: bool,
+
+    /// How to treat trips whose pickup falls outside the analyzed period [default: drop]
+    #[arg(long, global = true, value_enum)]
+    window: Option<WindowMode>,
+
+    /// Format of the results [default: table]
+    #[arg(long, global = true, value_enum)]
+    output_format: Option<OutputFormat>,
+
+    /// Write each result to <output-dir>/<name>.<ext> instead of stdout
+    /// (required for parquet and arrow-ipc)
+    #[arg(long, global = true)]
+    output_dir: Option<PathBuf>,
+
+    /// Aggregation options, also accepted without the `aggregate` subcommand
+    #[command(
+        flatten,
+        next_help_heading = "Aggregate options (without a subcommand)"
+    )]
+    aggregate: AggregateArgs,
+}
+
+/// Options of the `aggregate` command
+#[derive(clap::Args, Clone, Debug)]
+struct AggregateArgs {
+    /// Absolute tolerance when comparing float results of the DataFrame API and SQL [default: 1e-9]
+    #[arg(long)]
+    abs_tol: Option<f64>,
+
+    /// Relative tolerance when comparing float results of the DataFrame API and SQL [default: 1e-9]
+    #[arg(long)]
+    rel_tol: Option<f64>,
+
+    /// Skip the DataFrame-vs-SQL parity check
+    #[arg(long)]
+    no_parity: bool,
+
+    /// Add a human-readable label column next to payment type and other coded columns
+    #[arg(long)]
+    labels: bool,
+
+    /// Exact or approximate (t-digest) median and p90/p95/p99 [default: exact]
+    #[arg(long, value_enum)]
+    percentiles: Option<PercentileMode>,
+
+    /// List the available aggregations and exit
+    #[arg(long)]
+    list: bool,
+
+    /// Only run the named aggregation(s), repeatable or comma-separated
+    /// Example: --only trips_by_month,tips_by_payment_type
+    #[arg(long, value_delimiter = ',')]
+    only: Vec<String>,
+}
+
+/// Without a subcommand, all selected aggregations are run (like `aggregate`)
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run the aggregations with the DataFrame API and SQL and check their parity
+    Aggregate(AggregateArgs),
+    /// Check the data folders, file names and Parquet footers against the TLC data dictionary
+    /// (no data is scanned)
+    Validate,
+    /// Rows, row groups, sizes, pickup range and writer of each monthly file, read from the
+    /// Parquet footers (no data is scanned)
+    Inventory,
+    /// Show the columns of the trip table or of another registered table
+    Schema {
+        /// Table to describe, defaults to the trip table
+        #[arg(long, conflicts_with = "tables")]
+        table: Option<String>,
+
+        /// List the registered tables instead
+        #[arg(long)]
+        tables: bool,
+    },
+    /// Run one SQL statement (or several separated by ';') against the registered tables
+    /// Example: query "SELECT payment_type, COUNT(*) FROM yellow GROUP BY 1"
+    Query {
+        /// SQL text, `$name` placeholders are bound with --param
+        sql: String,
+
+        /// Named parameter bound to `$name` in the SQL, repeatable
+        #[arg(long = "param", value_parser = sql_files::parse_param)]
+        params: Vec<(String, String)>,
+    },
+    /// Stream a table or query result to a folder of Parquet, CSV or JSON files
+    /// Example: export --sql "SELECT * FROM yellow WHERE payment_type = 2" --path ./cash
+    Export {
+        /// Table to export, defaults to the trip table (time window applied)
+        #[arg(long, conflicts_with = "sql")]
+        table: Option<String>,
+
+        /// Query whose result is exported
+        #[arg(long)]
+        sql: Option<String>,
+
+        /// File format of the exported dataset
+        #[arg(long, value_enum, default_value_t = ExportFormat::Parquet)]
+        format: ExportFormat,
+
+        /// Folder the part files are written to, must be empty or missing
+        #[arg(long)]
+        path: PathBuf,
+
+        /// Write into a non-empty --path, deleting the part files of an earlier export in
+        /// the same format (other files are kept)
+        #[arg(long)]
+        overwrite: bool,
+    },
+    /// Time the DataFrame API and SQL implementation of the aggregations
+    Profile {
+        /// Only profile the named aggregation(s), repeatable or comma-separated
+        #[arg(long, value_delimiter = ',')]
+        only: Vec<String>,
+
+        /// Exact or approximate (t-digest) percentiles [default: exact]
+        #[arg(long, value_enum)]
+        percentiles: Option<PercentileMode>,
+
+        /// Also show the EXPLAIN ANALYZE plan of each SQL implementation
+        #[arg(long)]
+        explain: bool,
+    },
+    /// Serve the aggregations and ad-hoc SQL over HTTP (JSON, CSV or NDJSON)
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        addr: SocketAddr,
+
+        /// Add label columns next to coded columns
+        #[arg(long)]
+        labels: bool,
+
+        /// Exact or approximate (t-digest) percentiles [default: exact]
+        #[arg(long, value_enum)]
+        percentiles: Option<PercentileMode>,
+
+        /// Largest result returned, larger ones are refused (results are built in memory)
+        #[arg(long, default_value_t = 100_000)]
+        max_rows: usize,
+    },
+    /// Validate the --config file and the data folders it points to
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Interactive SQL shell over the registered TLC tables
+    Repl {
+        /// History file, defaults to ~/.nyc_tlc_datafusion_history
+        #[arg(long)]
+        history: Option<PathBuf>,
+    },
+    /// Data quality report: NULLs, invalid amounts, times, locations and codes per month
+    Quality {
+        /// Sample rows shown per rule (0 disables samples)
+        #[arg(long, default_value_t = 5)]
+        samples: usize,
+    },
+    /// Split the trip table into a cleaned and a quarantine Parquet dataset
+    Clean {
+        /// Rules a trip must pass to be kept, comma-separated
+        #[arg(long, value_enum, value_delimiter = ',', default_values_t = [
+            CleanRule::NegativeTotal,
+            CleanRule::DropoffBeforePickup,
+            CleanRule::OutOfYear,
+            CleanRule::UnknownPaymentType,
+        ])]
+        rules: Vec<CleanRule>,
+    },
+    /// Trips, median duration and average fare per pickup x dropoff zone, long and wide
+    /// Example: od-matrix --hours 7..10 --month 2025-03 --output-format parquet --output-dir ./out
+    OdMatrix {
+        /// Pickup hours to keep: one hour, a list (7,8,9) or a band (7..10, 22..=2)
+        #[arg(long, value_parser = od_matrix::parse_hours)]
+        hours: Option<Hours>,
+
+        /// Pickup month(s) to keep as YYYY-MM, repeatable or comma-separated
+        #[arg(long = "month", value_delimiter = ',', value_parser = od_matrix::parse_month)]
+        months: Vec<NaiveDate>,
+
+        /// Measure shown in the wide (pivoted) matrix
+        #[arg(long, value_enum, default_value_t = OdValue::TripCount)]
+        value: OdValue,
+    },
+    /// Run SQL files (or every .sql file of a folder) against the registered tables
+    /// Example: run --sql-file queries/*.sql --param year=2025 --param borough=Queens
+    Run {
+        /// SQL file(s) or folder(s) to execute, results are titled with the file name
+        #[arg(long = "sql-file", required = true, num_args = 1..)]
+        sql_files: Vec<PathBuf>,
+
+        /// Named parameter bound to `$name` in the SQL, repeatable
+        /// Example: --param start_date=2025-03-01
+        #[arg(long = "param", value_parser = sql_files::parse_param)]
+        params: Vec<(String, String)>,
+
+        /// File of `name = value` lines with parameter values (--param overrides it)
+        #[arg(long)]
+        params_file: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// Parse the file and check its values, the folders and file names (no data is read)
+    /// Example: --config configs/prod.toml config check
+    Check,
+}
+
+/// Prints the library's progress messages and warnings on stderr, as they are
+struct StderrLogger;
+
+impl log::Log for StderrLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::Level::Info && metadata.target().starts_with("nyc_tlc_datafusion")
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("{}", record.args());
+        }
+    }
+
+    fn flush(&self) {}
 }
 
+static LOGGER: StderrLogger = StderrLogger;
+
 #[tokio::main]
 async fn main() -> Result<()> {
+    log::set_logger(&LOGGER).map_err(|e| anyhow!("{}", e))?;
+    log::set_max_level(log::LevelFilter::Info);
+
     let args = Args::parse();
+    let config = args.settings()?;
 
-    let data_dir = PathBuf::from(&args.data_dir);
-    validate_data_dir(&data_dir, args.year)?;
-
-    let ctx = SessionContext::new();
-
-    // Register ALL parquet files in the directory as one table
-    ctx.register_parquet(
-        "yellow",
-        data_dir.to_string_lossy().as_ref(),
-        ParquetReadOptions::default(),
-    )
-    .await
-    .context("register_parquet failed")?;
-
-    println!("Loaded table 'yellow' from: {}", data_dir.display());
-    println!("Running aggregations for year {}...\n", args.year);
-
-    // ---------------------------------------------------
-    // Aggregation 1: Trips and revenue by pickup month
-    // ---------------------------------------------------
-    println!("==============================");
-    println!("Aggregation 1 (DataFrame API): Trips and revenue by pickup month");
-    println!("==============================");
-
-    let df = ctx.table("yellow").await?;
-
-
-    // pickup_month = date_trunc('month', tpep_pickup_datetime)
-   let pickup_month =
-    date_trunc(lit("month"), col("tpep_pickup_datetime")).alias("pickup_month");
-
-    let agg1_df = df
-        .aggregate(
-            vec![pickup_month],
-            vec![
-                count(lit(1)).alias("trip_count"),
-                sum(col("total_amount")).alias("total_revenue"),
-                avg(col("fare_amount")).alias("avg_fare"),
-            ],
-        )?
-        .sort(vec![col("pickup_month").sort(true, true)])?;
-
-    print_df("Aggregation 1 (DataFrame API)", agg1_df).await?;
-
-    println!("\n==============================");
-    println!("Aggregation 1 (SQL): Trips and revenue by pickup month");
-    println!("==============================");
-
-    let agg1_sql = r#"
-        SELECT
-            date_trunc('month', tpep_pickup_datetime) AS pickup_month,
-            COUNT(*) AS trip_count,
-            SUM(total_amount) AS total_revenue,
-            AVG(fare_amount) AS avg_fare
-        FROM yellow
-        GROUP BY 1
-        ORDER BY 1 ASC
-    "#;
-
-    let agg1_sql_df = ctx.sql(agg1_sql).await?;
-    print_df("Aggregation 1 (SQL)", agg1_sql_df).await?;
-
-    // ---------------------------------------------------
-    // Aggregation 2: Tip behavior by payment type
-    // ---------------------------------------------------
-    println!("\n==============================");
-    println!("Aggregation 2 (DataFrame API): Tip behavior by payment type");
-    println!("==============================");
-
-    let df2 = ctx.table("yellow").await?;
-
-    // tip_rate = SUM(tip_amount) / SUM(total_amount)
-    // Step 1: aggregate sums separately
-let agg2_base = df2.aggregate(
-    vec![col("payment_type")],
-    vec![
-        count(lit(1)).alias("trip_count"),
-        avg(col("tip_amount")).alias("avg_tip_amount"),
-        sum(col("tip_amount")).alias("sum_tip_amount"),
-        sum(col("total_amount")).alias("sum_total_amount"),
-    ],
-)?;
-
-// Step 2: compute tip_rate in a projection (and optionally drop helper columns)
-let agg2_df = agg2_base
-    .select(vec![
-        col("payment_type"),
-        col("trip_count"),
-        col("avg_tip_amount"),
-        (col("sum_tip_amount") / col("sum_total_amount")).alias("tip_rate"),
-    ])?
-    .sort(vec![col("trip_count").sort(false, true)])?;
-
-    print_df("Aggregation 2 (DataFrame API)", agg2_df).await?;
-
-    println!("\n==============================");
-    println!("Aggregation 2 (SQL): Tip behavior by payment type");
-    println!("==============================");
-
-    let agg2_sql = r#"
-        SELECT
-            payment_type,
-            COUNT(*) AS trip_count,
-            AVG(tip_amount) AS avg_tip_amount,
-            SUM(tip_amount) / SUM(total_amount) AS tip_rate
-        FROM yellow
-        GROUP BY 1
-        ORDER BY trip_count DESC
-    "#;
-
-    let agg2_sql_df = ctx.sql(agg2_sql).await?;
-    print_df("Aggregation 2 (SQL)", agg2_sql_df).await?;
-
-    println!("\n✅ All aggregations completed successfully.");
-    Ok(())
-}
+    match &args.command {
+        Some(Command::Aggregate(aggregate_args)) => aggregate(&config, aggregate_args).await,
+        Some(Command::Validate) => {
+            let builder = config.session_builder()?;
+            let (report, summary) = validate::validate(&builder)?;
+            config
+                .sink()?
+                .write("validate", "Monthly files", report.schema(), &[report])?;
+            if summary.unreadable + summary.incompatible > 0 {
+                return Err(anyhow!(
+                    "{} file(s) have an unreadable footer, {} are incompatible with the data dictionary",
+                    summary.unreadable,
+                    summary.incompatible
+                ));
+            }
+            match summary.drifted {
+                0 => eprintln!("\n✅ All files are readable and match the data dictionary."),
+                n => eprintln!(
+                    "\n✅ All files are readable; {} drift from the data dictionary but can be loaded.",
+                    n
+                ),
+            }
+            Ok(())
+        }
+        Some(Command::Inventory) => {
+            let builder = config.session_builder()?;
+            let listing = inventory::inventory(&builder)?;
+            config
+                .sink()?
+                .write("inventory", "Monthly files", listing.schema(), &[listing])
+        }
+        Some(Command::Schema { table, tables }) => {
+            let (session, sink) = load_session(&config).await?;
+            if *tables {
+                let names = schema::table_names(&session.ctx);
+                let fields = Schema::new(vec![Field::new("table", DataType::Utf8, false)]);
+                let batch = RecordBatch::try_new(
+                    Arc::new(fields),
+                    vec![Arc::new(StringArray::from(names))],
+                )?;
+                return sink.write("tables", "Registered tables", batch.schema(), &[batch]);
+            }
+            let table = table.as_deref().unwrap_or(&session.table);
+            let columns = schema::describe_table(&session.ctx, table).await?;
+            sink.write(
+                &format!("schema_{}", table),
+                &format!("Columns of {}", table),
+                columns.schema(),
+                &[columns],
+            )
+        }
+        Some(Command::Query { sql, params }) => {
+            let mut query_params = QueryParams::default();
+            query_params.extend(params.iter().cloned());
+            let (session, sink) = load_session(&config).await?;
+            sql_files::run_sql(&session.ctx, &sink, sql, "query", "query", &query_params).await
+        }
+        Some(Command::Export {
+            table,
+            sql,
+            format,
+            path,
+            overwrite,
+        }) => {
+            let (session, _) = load_session(&config).await?;
+            let df = match (table, sql) {
+                (_, Some(sql)) => session.ctx.sql(sql).await?,
+                (Some(table), None) if *table != session.table => {
+                    session.ctx.table(table.as_str()).await?
+                }
+                _ => session.trips().await?,
+            };
+            let rows = export::write_dataset(df, *format, path, *overwrite).await?;
+            eprintln!("Exported {} rows -> {}", rows, path.display());
+            Ok(())
+        }
+        Some(Command::Profile {
+            only,
+            percentiles,
+            explain,
+        }) => {
+            let settings = &config.aggregations;
+            let only = if only.is_empty() {
+                &settings.only
+            } else {
+                only
+            };
+            let percentiles = percentiles.unwrap_or(settings.percentiles());
 
-fn validate_data_dir(data_dir: &Path, year: i32) -> Result<()> {
-    if !data_dir.exists() {
-        return Err(anyhow!(
-            "Data directory does not exist: {}",
-            data_dir.display()
-        ));
-    }
-    if !data_dir.is_dir() {
-        return Err(anyhow!(
-            "Data path is not a directory: {}",
-            data_dir.display()
-        ));
+            let (session, sink) = load_session(&config).await?;
+            let input = session.aggregation_input(false, percentiles).await?;
+            let selected: Vec<_> = aggregations::select(only)?
+                .into_iter()
+                .filter(|agg| !agg.requires_zones() || input.zones.is_some())
+                .collect();
+            profile::profile(&selected, &input, &sink, *explain).await
+        }
+        Some(Command::Serve {
+            addr,
+            labels,
+            percentiles,
+            max_rows,
+        }) => {
+            let settings = &config.aggregations;
+            let labels = *labels || settings.labels();
+            let percentiles = percentiles.unwrap_or(settings.percentiles());
+
+            let (session, _) = load_session(&config).await?;
+            let input = session.aggregation_input(labels, percentiles).await?;
+            serve::serve(*addr, input, *max_rows).await
+        }
+        Some(Command::Config {
+            command: ConfigCommand::Check,
+        }) => {
+            let Some(path) = &args.config else {
+                return Err(anyhow!("config check needs --config <file>"));
+            };
+            let (report, failed) = config::check(&config)?;
+            // An invalid [output] is one of the reported problems, print to stdout then
+            let sink = config
+                .sink()
+                .or_else(|_| OutputSink::new(OutputFormat::Table, None))?;
+            sink.write("config_check", "Settings", report.schema(), &[report])?;
+            if failed > 0 {
+                return Err(anyhow!("{} check(s) failed for {}", failed, path.display()));
+            }
+            eprintln!("\n✅ {} is valid.", path.display());
+            Ok(())
+        }
+        Some(Command::Repl { history }) => {
+            let (session, sink) = load_session(&config).await?;
+            repl::run(&session.ctx, &sink, history.clone()).await
+        }
+        Some(Command::Quality { samples }) => {
+            let (session, sink) = load_session(&config).await?;
+            let trips = session.raw_trips().await?;
+            quality::report(trips, session.dataset, &session.window, &sink, *samples).await
+        }
+        Some(Command::Clean { rules }) => {
+            if config.dataset.cleaned.unwrap_or(false) {
+                return Err(anyhow!("clean reads the raw files, drop --cleaned"));
+            }
+            let (session, sink) = load_session(&config).await?;
+            let trips = session.raw_trips().await?;
+            let dirs = CleanDirs::new(&config.clean_dir());
+            clean::clean(trips, rules, &session.window, &dirs, &sink).await
+        }
+        Some(Command::OdMatrix {
+            hours,
+            months,
+            value,
+        }) => {
+            let (session, sink) = load_session(&config).await?;
+            let trips = session.raw_trips().await?;
+            let filter = OdFilter {
+                hours: hours.clone().map(|h| h.0).unwrap_or_default(),
+                months: months.clone(),
+            };
+            od_matrix::export(trips, &session.window, &filter, *value, &sink).await
+        }
+        Some(Command::Run {
+            sql_files,
+            params,
+            params_file,
+        }) => run_sql_files(&config, sql_files, params, params_file.as_deref()).await,
+        None => aggregate(&config, &args.aggregate).await,
     }
+}
+
+/// Build the session and output sink of the run settings
+async fn load_session(config: &Config) -> Result<(TlcSession, OutputSink)> {
+    let sink = config.sink()?;
+    Ok((config.session_builder()?.build().await?, sink))
+}
 
-    // Warning-only check: expect 12 files following TLC naming pattern
-    let mut missing = Vec::new();
-    for m in 1..=12 {
-        let fname = format!("yellow_tripdata_{}-{:02}.parquet", year, m);
-        if !data_dir.join(&fname).exists() {
-            missing.push(fname);
+/// Run the selected aggregations with both APIs and check their parity
+async fn aggregate(config: &Config, aggregate_args: &AggregateArgs) -> Result<()> {
+    if aggregate_args.list {
+        for agg in aggregations::registry() {
+            println!("{:<24} {}", agg.name(), agg.description());
         }
+        return Ok(());
     }
+    let mut settings = config.aggregations.clone();
+    aggregate_args.apply(&mut settings);
+    let selected = aggregations::select(&settings.only)?;
 
-    if !missing.is_empty() {
+    let (session, sink) = load_session(config).await?;
+    let (years, window) = (&session.years, &session.window);
+
+    let (first_year, last_year) = (years[0], years[years.len() - 1]);
+    if first_year == last_year {
+        eprintln!("Running aggregations for year {}...", first_year);
+    } else {
         eprintln!(
-            "⚠️  Some expected files are missing in {}:",
-            data_dir.display()
+            "Running aggregations for years {}..={} ({} years)...",
+            first_year,
+            last_year,
+            years.len()
         );
-        for f in &missing {
-            eprintln!("   - {}", f);
+    }
+    eprintln!("Pickup time window: {} (mode: {})\n", window, window.mode);
+
+    let outside = window.count_outside(session.raw_trips().await?).await?;
+    eprintln!("{}", window.report(outside));
+
+    let input = session
+        .aggregation_input(settings.labels(), settings.percentiles())
+        .await?;
+
+    let tolerance = settings.tolerance();
+    let mut parity_failures = Vec::new();
+
+    for agg in &selected {
+        if agg.requires_zones() && input.zones.is_none() {
+            if !settings.only.is_empty() {
+                return Err(anyhow!(
+                    "{} needs the taxi zone lookup, pass --zones <path>",
+                    agg.name()
+                ));
+            }
+            eprintln!("\n⚠️  Skipping {}: taxi zone lookup not loaded", agg.name());
+            continue;
+        }
+
+        if let Some(report) = run_aggregation(agg.as_ref(), &input, &sink, tolerance).await? {
+            eprintln!("{}", report);
+            if !report.is_ok() {
+                parity_failures.push(agg.name());
+            }
         }
-        eprintln!("\nTip: you can test with 1 month first and later add all 12 months.\n");
     }
 
+    if !parity_failures.is_empty() {
+        return Err(anyhow!(
+            "DataFrame API and SQL results differ for: {}",
+            parity_failures.join(", ")
+        ));
+    }
+
+    eprintln!("\n✅ All aggregations completed successfully.");
     Ok(())
 }
 
-async fn print_df(title: &str, df: DataFrame) -> Result<()> {
-    let batches = df.collect().await?;
-    let formatted = pretty_format_batches(&batches)?;
-    println!("\n--- {} ---", title);
-    println!("{}", formatted);
+/// Execute each SQL file with the given parameters, in order
+async fn run_sql_files(
+    config: &Config,
+    paths: &[PathBuf],
+    cli_params: &[(String, String)],
+    params_file: Option<&Path>,
+) -> Result<()> {
+    let files = sql_files::expand_sql_files(paths)?;
+    if files.is_empty() {
+        return Err(anyhow!("No .sql files found in the given --sql-file paths"));
+    }
+
+    let mut params = match params_file {
+        Some(path) => QueryParams::from_file(path)?,
+        None => QueryParams::default(),
+    };
+    params.extend(cli_params.iter().cloned());
+
+    let (session, sink) = load_session(config).await?;
+    for path in &files {
+        sql_files::run_sql_file(&session.ctx, &sink, path, &params).await?;
+    }
+
+    eprintln!("\n✅ Ran {} SQL file(s).", files.len());
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Replace `target` when the flag was given
+fn set<T: Clone>(target: &mut Option<T>, flag: &Option<T>) {
+    if flag.is_some() {
+        *target = flag.clone();
+    }
+}
+
+impl Args {
+    /// Settings of this run: the `--config` file (if any) overridden by the flags given
+    fn settings(&self) -> Result<Config> {
+        let mut config = match &self.config {
+            Some(path) => Config::load(path)?,
+            None => Config::default(),
+        };
+
+        let dataset = &mut config.dataset;
+        set(&mut dataset.name, &self.dataset);
+        set(&mut dataset.data_root, &self.data_root);
+        set(&mut dataset.data_dir, &self.data_dir);
+        set(&mut dataset.years, &self.year.map(|y| vec![y]));
+        set(&mut dataset.years, &self.years.clone().map(|y| y.0));
+        set(&mut dataset.zones, &self.zones);
+        set(&mut dataset.clean_dir, &self.clean_dir);
+        if self.cleaned {
+            dataset.cleaned = Some(true);
+        }
+        if self.strict_schema {
+            dataset.strict_schema = Some(true);
+        }
+
+        let filters = &mut config.filters;
+        set(&mut filters.from, &self.from);
+        set(&mut filters.to, &self.to);
+        set(&mut filters.window, &self.window);
+
+        set(&mut config.output.format, &self.output_format);
+        set(&mut config.output.dir, &self.output_dir);
+        Ok(config)
+    }
+}
+
+impl AggregateArgs {
+    /// Override the `[aggregations]` settings with the flags given
+    fn apply(&self, settings: &mut AggregationsConfig) {
+        if !self.only.is_empty() {
+            settings.only = self.only.clone();
+        }
+        if self.labels {
+            settings.labels = Some(true);
+        }
+        if self.no_parity {
+            settings.parity = Some(false);
+        }
+        set(&mut settings.percentiles, &self.percentiles);
+        set(&mut settings.abs_tol, &self.abs_tol);
+        set(&mut settings.rel_tol, &self.rel_tol);
+    }
+}
+
+/// Sorted, de-duplicated list of years given with `--years`
+#[derive(Clone, Debug)]
+struct Years(Vec<i32>);
+
+/// Parse a year with TLC trip records
+fn parse_year(s: &str) -> Result<i32, String> {
+    let year = s
+        .trim()
+        .parse::<i32>()
+        .map_err(|_| format!("invalid year '{}'", s.trim()))?;
+    check_year(year).map_err(|e| e.to_string())?;
+    Ok(year)
+}
+
+/// Parse `--years`: a single year, a list (`2023,2025`) or a range (`2019..=2025`, `2019..2025`)
+fn parse_years(s: &str) -> Result<Years, String> {
+    // Range bounds are checked like single years, which also keeps ranges small
+    let mut years: Vec<i32> = if let Some((a, b)) = s.split_once("..=") {
+        (parse_year(a)?..=parse_year(b)?).collect()
+    } else if let Some((a, b)) = s.split_once("..") {
+        (parse_year(a)?..parse_year(b)?).collect()
+    } else {
+        s.split(',').map(parse_year).collect::<Result<_, _>>()?
+    };
+
+    years.sort_unstable();
+    years.dedup();
+    if years.is_empty() {
+        return Err(format!("empty year range '{}'", s));
+    }
+    Ok(Years(years))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn years(s: &str) -> Result<Vec<i32>, String> {
+        parse_years(s).map(|Years(years)| years)
+    }
+
+    #[test]
+    fn parses_single_years_lists_and_ranges() {
+        assert_eq!(years("2024"), Ok(vec![2024]));
+        assert_eq!(years("2025, 2023,2025"), Ok(vec![2023, 2025]));
+        assert_eq!(years("2019..=2021"), Ok(vec![2019, 2020, 2021]));
+        assert_eq!(years("2019..2021"), Ok(vec![2019, 2020]));
+    }
+
+    #[test]
+    fn rejects_empty_ranges_and_garbage() {
+        assert!(years("2021..2021").is_err());
+        assert!(years("2022..=2020").is_err());
+        assert!(years("twenty").is_err());
+        assert!(years("2020,").is_err());
+    }
+
+    #[test]
+    fn rejects_years_without_trip_records() {
+        assert!(years("2008").is_err());
+        assert!(years("2008..=2010").is_err());
+        assert!(years("2009..=2000000000").is_err());
+        assert!(years("0..=2000000000").is_err());
+        assert_eq!(years("2009"), Ok(vec![2009]));
+    }
+
+    #[test]
+    fn flags_override_the_config_file() {
+        let path = std::env::temp_dir().join(format!("tlc_settings_{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"
+            [dataset]
+            name = "green"
+            years = [2023, 2024]
+            zones = "/data/zones.csv"
+
+            [filters]
+            from = 2023-02-01
+            window = "isolate"
+
+            [output]
+            format = "csv"
+            "#,
+        )
+        .unwrap();
+        let args = Args::try_parse_from([
+            "nyc_tlc_datafusion",
+            "--config",
+            path.to_str().unwrap(),
+            "--years",
+            "2025,2024",
+            "--from",
+            "2024-06-01",
+            "--output-format",
+            "json",
+        ])
+        .unwrap();
+        let config = args.settings();
+        std::fs::remove_file(&path).unwrap();
+        let config = config.unwrap();
+
+        // Flags win, values without a flag come from the file
+        assert_eq!(config.years(), vec![2024, 2025]);
+        assert_eq!(config.filters.from, NaiveDate::from_ymd_opt(2024, 6, 1));
+        assert_eq!(config.output.format, Some(OutputFormat::Json));
+        assert_eq!(config.dataset(), Dataset::Green);
+        assert_eq!(config.filters.window, Some(WindowMode::Isolate));
+        assert_eq!(
+            config.dataset.zones,
+            Some(std::path::PathBuf::from("/data/zones.csv"))
+        );
+    }
+}
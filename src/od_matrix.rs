@@ -0,0 +1,335 @@
+//! Origin-destination matrix of the `od-matrix` command.
+
+use crate::aggregations::MULTI_DAY_MINUTES;
+use crate::output::OutputSink;
+use crate::window::TimeWindow;
+use anyhow::{anyhow, Result};
+use chrono::NaiveDate;
+use clap::ValueEnum;
+use datafusion::arrow::array::{Array, ArrayRef, Float64Array, Int64Array, RecordBatch};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use datafusion::functions::datetime::expr_fn::{date_part, date_trunc};
+use datafusion::functions_aggregate::expr_fn::{avg, count, median};
+use datafusion::prelude::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+/// Measure spread across the columns of the wide matrix
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OdValue {
+    TripCount,
+    MedianDurationMinutes,
+    AvgFare,
+}
+
+impl OdValue {
+    fn column(self) -> &'static str {
+        match self {
+            OdValue::TripCount => "trip_count",
+            OdValue::MedianDurationMinutes => "median_duration_minutes",
+            OdValue::AvgFare => "avg_fare",
+        }
+    }
+}
+
+/// Optional filters of the matrix
+pub struct OdFilter {
+    /// Pickup hours (0-23) to keep, all if empty
+    pub hours: Vec<i64>,
+    /// Pickup months (first day of the month) to keep, all if empty
+    pub months: Vec<NaiveDate>,
+}
+
+/// Pickup hours given with `--hours`
+#[derive(Clone, Debug)]
+pub struct Hours(pub Vec<i64>);
+
+/// Parse `--hours`: one hour, a list (`7,8,9`) or a band (`7..10`, `7..=9`); a band whose
+/// start is after its end wraps around midnight (`22..=2`)
+pub fn parse_hours(s: &str) -> Result<Hours, String> {
+    let parse = |h: &str| match h.trim().parse::<i64>() {
+        Ok(h) if (0..24).contains(&h) => Ok(h),
+        _ => Err(format!("invalid hour '{}', expected 0-23", h.trim())),
+    };
+    let band = |start: i64, end: i64| -> Vec<i64> {
+        if start <= end {
+            (start..=end).collect()
+        } else {
+            (start..24).chain(0..=end).collect()
+        }
+    };
+
+    let hours = if let Some((a, b)) = s.split_once("..=") {
+        band(parse(a)?, parse(b)?)
+    } else if let Some((a, b)) = s.split_once("..") {
+        let (start, end) = (parse(a)?, parse(b)?);
+        if start == end {
+            return Err(format!("empty hour band '{}'", s));
+        }
+        band(start, (end + 23) % 24)
+    } else {
+        s.split(',').map(parse).collect::<Result<_, _>>()?
+    };
+    Ok(Hours(hours))
+}
+
+/// Parse `--month YYYY-MM` into the first day of that month
+pub fn parse_month(s: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(&format!("{}-01", s.trim()), "%Y-%m-%d")
+        .map_err(|_| format!("invalid month '{}', expected YYYY-MM", s.trim()))
+}
+
+/// Trips, median duration and average fare per pickup x dropoff zone (long form)
+pub fn long_form(trips: DataFrame, window: &TimeWindow, filter: &OdFilter) -> Result<DataFrame> {
+    let mut trips = window.filter(trips)?;
+    if !filter.hours.is_empty() {
+        let hour = cast(
+            date_part(lit("hour"), col("pickup_datetime")),
+            DataType::Int64,
+        );
+        trips =
+            trips.filter(hour.in_list(filter.hours.iter().map(|h| lit(*h)).collect(), false))?;
+    }
+    if !filter.months.is_empty() {
+        let months = filter
+            .months
+            .iter()
+            .map(|m| {
+                cast(
+                    lit(format!("{}T00:00:00", m)),
+                    DataType::Timestamp(TimeUnit::Microsecond, None),
+                )
+            })
+            .collect();
+        trips = trips
+            .filter(date_trunc(lit("month"), col("pickup_datetime")).in_list(months, false))?;
+    }
+
+    // Median over plausible durations only, as in duration_speed_by_*
+    let valid = col("trip_duration_minutes")
+        .gt(lit(0.0))
+        .and(col("trip_duration_minutes").lt(lit(MULTI_DAY_MINUTES)));
+
+    Ok(trips
+        .aggregate(
+            vec![col("pu_location_id"), col("do_location_id")],
+            vec![
+                count(lit(1)).alias("trip_count"),
+                median(when(valid, col("trip_duration_minutes")).end()?)
+                    .alias("median_duration_minutes"),
+                avg(col("fare_amount")).alias("avg_fare"),
+            ],
+        )?
+        .sort(vec![
+            col("pu_location_id").sort(true, true),
+            col("do_location_id").sort(true, true),
+        ])?)
+}
+
+/// Pivot the long form: one row per pickup zone, one `do_<id>` column per dropoff zone.
+///
+/// Pairs without trips are 0 for `trip_count` and NULL for the other measures. Trips with
+/// an unknown (NULL) location are left out.
+pub fn wide_form(batches: &[RecordBatch], value: OdValue) -> Result<RecordBatch> {
+    let mut cells: BTreeMap<(i64, i64), Option<f64>> = BTreeMap::new();
+    let mut counts: BTreeMap<(i64, i64), i64> = BTreeMap::new();
+    for batch in batches {
+        let ids = |name: &str| {
+            batch
+                .column_by_name(name)
+                .and_then(|c| c.as_any().downcast_ref::<Int64Array>())
+                .ok_or_else(|| anyhow!("OD matrix has no Int64 {}", name))
+        };
+        let (pickup, dropoff) = (ids("pu_location_id")?, ids("do_location_id")?);
+        let values = batch
+            .column_by_name(value.column())
+            .ok_or_else(|| anyhow!("OD matrix has no {}", value.column()))?;
+        for row in 0..batch.num_rows() {
+            if pickup.is_null(row) || dropoff.is_null(row) {
+                continue;
+            }
+            let pair = (pickup.value(row), dropoff.value(row));
+            if let Some(trips) = values.as_any().downcast_ref::<Int64Array>() {
+                counts.insert(pair, trips.value(row));
+            } else if let Some(v) = values.as_any().downcast_ref::<Float64Array>() {
+                cells.insert(pair, (!v.is_null(row)).then(|| v.value(row)));
+            }
+        }
+    }
+
+    let pairs: Vec<(i64, i64)> = match value {
+        OdValue::TripCount => counts.keys().copied().collect(),
+        _ => cells.keys().copied().collect(),
+    };
+    let pickups: BTreeSet<i64> = pairs.iter().map(|(p, _)| *p).collect();
+    let dropoffs: BTreeSet<i64> = pairs.iter().map(|(_, d)| *d).collect();
+
+    let mut fields = vec![Field::new("pu_location_id", DataType::Int64, false)];
+    let mut columns: Vec<ArrayRef> = vec![Arc::new(Int64Array::from_iter_values(
+        pickups.iter().copied(),
+    ))];
+    for d in &dropoffs {
+        let name = format!("do_{}", d);
+        match value {
+            OdValue::TripCount => {
+                fields.push(Field::new(name, DataType::Int64, false));
+                columns.push(Arc::new(Int64Array::from_iter_values(
+                    pickups
+                        .iter()
+                        .map(|p| counts.get(&(*p, *d)).copied().unwrap_or(0)),
+                )));
+            }
+            _ => {
+                fields.push(Field::new(name, DataType::Float64, true));
+                columns.push(Arc::new(Float64Array::from_iter(
+                    pickups
+                        .iter()
+                        .map(|p| cells.get(&(*p, *d)).copied().flatten()),
+                )));
+            }
+        }
+    }
+
+    Ok(RecordBatch::try_new(
+        Arc::new(Schema::new(fields)),
+        columns,
+    )?)
+}
+
+/// Emit the long (`od_matrix`) and wide (`od_matrix_wide`) matrices to `sink`
+pub async fn export(
+    trips: DataFrame,
+    window: &TimeWindow,
+    filter: &OdFilter,
+    value: OdValue,
+    sink: &OutputSink,
+) -> Result<()> {
+    let long = long_form(trips, window, filter)?;
+    let batches = sink
+        .emit("od_matrix", "OD matrix (pickup x dropoff zone)", long)
+        .await?;
+
+    let wide = wide_form(&batches, value)?;
+    sink.write(
+        "od_matrix_wide",
+        &format!(
+            "OD matrix ({}, pickup zone rows x dropoff zone columns)",
+            value.column()
+        ),
+        wide.schema(),
+        &[wide],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::AsArray;
+    use datafusion::arrow::datatypes::{Float64Type, Int64Type};
+
+    fn hours(s: &str) -> Result<Vec<i64>, String> {
+        parse_hours(s).map(|Hours(hours)| hours)
+    }
+
+    #[test]
+    fn parses_hours_lists_and_bands() {
+        assert_eq!(hours("7"), Ok(vec![7]));
+        assert_eq!(hours("7, 8,9"), Ok(vec![7, 8, 9]));
+        assert_eq!(hours("7..10"), Ok(vec![7, 8, 9]));
+        assert_eq!(hours("7..=9"), Ok(vec![7, 8, 9]));
+        assert_eq!(hours("0..=23").map(|h| h.len()), Ok(24));
+    }
+
+    #[test]
+    fn hour_bands_wrap_around_midnight() {
+        assert_eq!(hours("22..=2"), Ok(vec![22, 23, 0, 1, 2]));
+        assert_eq!(hours("22..2"), Ok(vec![22, 23, 0, 1]));
+        assert_eq!(hours("22..0"), Ok(vec![22, 23]));
+        assert_eq!(hours("5..=5"), Ok(vec![5]));
+    }
+
+    #[test]
+    fn rejects_invalid_hours() {
+        assert!(hours("24").is_err());
+        assert!(hours("-1").is_err());
+        assert!(hours("7..24").is_err());
+        assert!(hours("5..5").is_err());
+        assert!(hours("seven").is_err());
+    }
+
+    fn long(
+        pickup: Vec<Option<i64>>,
+        dropoff: Vec<Option<i64>>,
+        fare: Vec<Option<f64>>,
+    ) -> RecordBatch {
+        let trips: Vec<i64> = (1..=pickup.len() as i64).collect();
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("pu_location_id", DataType::Int64, true),
+            Field::new("do_location_id", DataType::Int64, true),
+            Field::new("trip_count", DataType::Int64, false),
+            Field::new("avg_fare", DataType::Float64, true),
+        ]));
+        RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int64Array::from(pickup)) as ArrayRef,
+                Arc::new(Int64Array::from(dropoff)),
+                Arc::new(Int64Array::from(trips)),
+                Arc::new(Float64Array::from(fare)),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn wide_trip_counts_fill_missing_pairs_with_zero() {
+        let batches = [
+            long(
+                vec![Some(1), Some(1)],
+                vec![Some(10), Some(20)],
+                vec![None, None],
+            ),
+            long(
+                vec![Some(2), None],
+                vec![Some(20), Some(10)],
+                vec![None, None],
+            ),
+        ];
+        let wide = wide_form(&batches, OdValue::TripCount).unwrap();
+
+        let names: Vec<&str> = wide
+            .schema_ref()
+            .fields()
+            .iter()
+            .map(|f| f.name().as_str())
+            .collect();
+        assert_eq!(names, ["pu_location_id", "do_10", "do_20"]);
+        let column = |i: usize| wide.column(i).as_primitive::<Int64Type>().values().to_vec();
+        assert_eq!(column(0), [1, 2]);
+        assert_eq!(column(1), [1, 0]);
+        assert_eq!(column(2), [2, 1]);
+    }
+
+    #[test]
+    fn wide_measures_leave_missing_pairs_null() {
+        let batches = [long(
+            vec![Some(1), Some(2), Some(2)],
+            vec![Some(10), Some(10), Some(20)],
+            vec![Some(12.5), None, Some(30.0)],
+        )];
+        let wide = wide_form(&batches, OdValue::AvgFare).unwrap();
+
+        let do_10 = wide.column(1).as_primitive::<Float64Type>();
+        let do_20 = wide.column(2).as_primitive::<Float64Type>();
+        assert_eq!(do_10.value(0), 12.5);
+        assert!(do_10.is_null(1));
+        assert!(do_20.is_null(0));
+        assert_eq!(do_20.value(1), 30.0);
+    }
+
+    #[test]
+    fn wide_form_needs_the_value_column() {
+        let batches = [long(vec![Some(1)], vec![Some(10)], vec![Some(1.0)])];
+        assert!(wide_form(&batches, OdValue::MedianDurationMinutes).is_err());
+    }
+}
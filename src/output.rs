@@ -0,0 +1,197 @@
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use datafusion::arrow::array::RecordBatch;
+use datafusion::arrow::csv;
+use datafusion::arrow::datatypes::SchemaRef;
+use datafusion::arrow::ipc::writer::FileWriter;
+use datafusion::arrow::json::{ArrayWriter, LineDelimitedWriter};
+use datafusion::arrow::util::display::{ArrayFormatter, FormatOptions};
+use datafusion::arrow::util::pretty::pretty_format_batches;
+use datafusion::parquet::arrow::ArrowWriter;
+use datafusion::prelude::*;
+use log::info;
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Output format for aggregation results
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// ASCII table (pretty_format_batches)
+    Table,
+    Csv,
+    /// JSON array of row objects
+    Json,
+    /// Newline-delimited JSON, one row object per line
+    Ndjson,
+    Parquet,
+    /// Arrow IPC file format
+    ArrowIpc,
+    /// GitHub-flavored Markdown table
+    Markdown,
+}
+
+impl OutputFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Table => "txt",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Json => "json",
+            OutputFormat::Ndjson => "ndjson",
+            OutputFormat::Parquet => "parquet",
+            OutputFormat::ArrowIpc => "arrow",
+            OutputFormat::Markdown => "md",
+        }
+    }
+
+    /// Binary formats can only be written to files
+    pub fn is_binary(self) -> bool {
+        matches!(self, OutputFormat::Parquet | OutputFormat::ArrowIpc)
+    }
+}
+
+/// Where and how aggregation results are emitted.
+///
+/// Without an output directory results go to stdout; with one, every result is
+/// written to `<dir>/<name>.<ext>`. Titles and progress messages are logged.
+#[derive(Clone, Debug)]
+pub struct OutputSink {
+    format: OutputFormat,
+    dir: Option<PathBuf>,
+}
+
+impl OutputSink {
+    pub fn new(format: OutputFormat, dir: Option<PathBuf>) -> Result<Self> {
+        if format.is_binary() && dir.is_none() {
+            return Err(anyhow!(
+                "Binary output formats (parquet, arrow-ipc) need --output-dir"
+            ));
+        }
+        if let Some(dir) = &dir {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Cannot create output directory {}", dir.display()))?;
+        }
+        Ok(Self { format, dir })
+    }
+
+    /// Collect `df`, emit it under `name` and return the batches for further checks
+    pub async fn emit(&self, name: &str, title: &str, df: DataFrame) -> Result<Vec<RecordBatch>> {
+        let schema: SchemaRef = Arc::new(df.schema().as_arrow().clone());
+        let batches = df.collect().await?;
+        self.write(name, title, schema, &batches)?;
+        Ok(batches)
+    }
+
+    /// Emit already collected batches under `name`
+    pub fn write(
+        &self,
+        name: &str,
+        title: &str,
+        schema: SchemaRef,
+        batches: &[RecordBatch],
+    ) -> Result<()> {
+        // Titles and progress are logged so stdout only carries the data
+        info!("\n--- {} ---", title);
+
+        match &self.dir {
+            Some(dir) => {
+                let path = dir.join(format!("{}.{}", name, self.format.extension()));
+                let file = File::create(&path)
+                    .with_context(|| format!("Cannot create {}", path.display()))?;
+                write_batches(self.format, file, schema, batches)
+                    .with_context(|| format!("Cannot write {}", path.display()))?;
+                info!("Wrote {} rows to {}", row_count(batches), path.display());
+            }
+            None => {
+                write_batches(self.format, std::io::stdout(), schema, batches)?;
+                // Only the table and JSON writers stop without a line break
+                if matches!(self.format, OutputFormat::Table | OutputFormat::Json) {
+                    println!();
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn row_count(batches: &[RecordBatch]) -> usize {
+    batches.iter().map(|b| b.num_rows()).sum()
+}
+
+/// Serialize `batches` in `format`; `schema` is used so empty results keep their columns
+pub fn write_batches<W: Write + Send>(
+    format: OutputFormat,
+    mut w: W,
+    schema: SchemaRef,
+    batches: &[RecordBatch],
+) -> Result<()> {
+    match format {
+        OutputFormat::Table => {
+            write!(w, "{}", pretty_format_batches(batches)?)?;
+        }
+        OutputFormat::Csv => {
+            let mut writer = csv::WriterBuilder::new().with_header(true).build(w);
+            if batches.is_empty() {
+                writer.write(&RecordBatch::new_empty(schema))?;
+            }
+            for batch in batches {
+                writer.write(batch)?;
+            }
+        }
+        OutputFormat::Json => {
+            let mut writer = ArrayWriter::new(w);
+            for batch in batches {
+                writer.write(batch)?;
+            }
+            // Writes `[]` for zero rows
+            writer.finish()?;
+        }
+        OutputFormat::Ndjson => {
+            let mut writer = LineDelimitedWriter::new(w);
+            for batch in batches {
+                writer.write(batch)?;
+            }
+            writer.finish()?;
+        }
+        OutputFormat::Parquet => {
+            let mut writer = ArrowWriter::try_new(w, schema, None)?;
+            for batch in batches {
+                writer.write(batch)?;
+            }
+            writer.close()?;
+        }
+        OutputFormat::ArrowIpc => {
+            let mut writer = FileWriter::try_new(w, &schema)?;
+            for batch in batches {
+                writer.write(batch)?;
+            }
+            writer.finish()?;
+        }
+        OutputFormat::Markdown => write_markdown(w, &schema, batches)?,
+    }
+    Ok(())
+}
+
+fn write_markdown<W: Write>(mut w: W, schema: &SchemaRef, batches: &[RecordBatch]) -> Result<()> {
+    let names: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+    writeln!(w, "| {} |", names.join(" | "))?;
+    writeln!(w, "|{}", "---|".repeat(names.len()))?;
+
+    let options = FormatOptions::default().with_null("");
+    for batch in batches {
+        let formatters = batch
+            .columns()
+            .iter()
+            .map(|c| ArrayFormatter::try_new(c.as_ref(), &options))
+            .collect::<Result<Vec<_>, _>>()?;
+        for row in 0..batch.num_rows() {
+            let cells: Vec<String> = formatters
+                .iter()
+                .map(|f| f.value(row).to_string().replace('|', "\\|"))
+                .collect();
+            writeln!(w, "| {} |", cells.join(" | "))?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,262 @@
+use anyhow::Result;
+use datafusion::arrow::array::RecordBatch;
+use datafusion::arrow::compute::concat_batches;
+use datafusion::common::ScalarValue;
+use std::fmt;
+
+/// Maximum number of mismatched cells printed per aggregation
+const MAX_REPORTED: usize = 20;
+
+/// Float tolerance used when comparing DataFrame and SQL results.
+///
+/// Two floats are equal if they are within `abs` of each other, or within
+/// `rel` times the larger magnitude.
+#[derive(Clone, Copy, Debug)]
+pub struct Tolerance {
+    pub abs: f64,
+    pub rel: f64,
+}
+
+impl Tolerance {
+    fn float_eq(&self, a: f64, b: f64) -> bool {
+        // Infinities only equal themselves: their difference is NaN or infinite
+        if a == b || (a.is_nan() && b.is_nan()) {
+            return true;
+        }
+        if a.is_infinite() || b.is_infinite() {
+            return false;
+        }
+        let diff = (a - b).abs();
+        diff <= self.abs || diff <= self.rel * a.abs().max(b.abs())
+    }
+}
+
+/// A single cell that differs between the two result sets
+#[derive(Debug)]
+pub struct Mismatch {
+    pub row: usize,
+    pub column: String,
+    pub left: String,
+    pub right: String,
+}
+
+/// Outcome of comparing the DataFrame API result with the SQL result
+#[derive(Debug)]
+pub struct ParityReport {
+    pub title: String,
+    pub rows: usize,
+    /// Problems that make a cell-by-cell comparison impossible (schema, row count)
+    pub shape_errors: Vec<String>,
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl ParityReport {
+    pub fn is_ok(&self) -> bool {
+        self.shape_errors.is_empty() && self.mismatches.is_empty()
+    }
+}
+
+impl fmt::Display for ParityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_ok() {
+            return write!(f, "✅ Parity OK: {} ({} rows)", self.title, self.rows);
+        }
+
+        writeln!(f, "❌ Parity FAILED: {}", self.title)?;
+        for e in &self.shape_errors {
+            writeln!(f, "   - {}", e)?;
+        }
+        for m in self.mismatches.iter().take(MAX_REPORTED) {
+            writeln!(
+                f,
+                "   - row {}, column '{}': DataFrame={} SQL={}",
+                m.row, m.column, m.left, m.right
+            )?;
+        }
+        if self.mismatches.len() > MAX_REPORTED {
+            writeln!(
+                f,
+                "   ... and {} more mismatched cells",
+                self.mismatches.len() - MAX_REPORTED
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn row_count(batches: &[RecordBatch]) -> usize {
+    batches.iter().map(|b| b.num_rows()).sum()
+}
+
+/// Compare the DataFrame API (`left`) and SQL (`right`) results row by row.
+///
+/// Column names and types must match; floats are compared with `tolerance`,
+/// every other value must be equal (NULL equals NULL).
+pub fn compare_batches(
+    title: &str,
+    left: &[RecordBatch],
+    right: &[RecordBatch],
+    tolerance: Tolerance,
+) -> Result<ParityReport> {
+    let mut report = ParityReport {
+        title: title.to_string(),
+        rows: 0,
+        shape_errors: Vec::new(),
+        mismatches: Vec::new(),
+    };
+
+    // A side without batches has no schema to compare, but may face only empty batches
+    let (Some(left_first), Some(right_first)) = (left.first(), right.first()) else {
+        let (left_rows, right_rows) = (row_count(left), row_count(right));
+        report.rows = left_rows;
+        if left_rows != right_rows {
+            report.shape_errors.push(format!(
+                "row count differs: DataFrame={} SQL={}",
+                left_rows, right_rows
+            ));
+        }
+        return Ok(report);
+    };
+
+    let left = concat_batches(&left_first.schema(), left)?;
+    let right = concat_batches(&right_first.schema(), right)?;
+    report.rows = left.num_rows();
+
+    let left_fields = left.schema().fields().clone();
+    let right_fields = right.schema().fields().clone();
+    if left_fields.len() != right_fields.len() {
+        report.shape_errors.push(format!(
+            "column count differs: DataFrame={} SQL={}",
+            left_fields.len(),
+            right_fields.len()
+        ));
+    }
+    for (l, r) in left_fields.iter().zip(right_fields.iter()) {
+        if l.name() != r.name() || l.data_type() != r.data_type() {
+            report.shape_errors.push(format!(
+                "column differs: DataFrame={} {} SQL={} {}",
+                l.name(),
+                l.data_type(),
+                r.name(),
+                r.data_type()
+            ));
+        }
+    }
+    if left.num_rows() != right.num_rows() {
+        report.shape_errors.push(format!(
+            "row count differs: DataFrame={} SQL={}",
+            left.num_rows(),
+            right.num_rows()
+        ));
+    }
+    if !report.shape_errors.is_empty() {
+        return Ok(report);
+    }
+
+    for (i, field) in left_fields.iter().enumerate() {
+        let (l_col, r_col) = (left.column(i), right.column(i));
+        for row in 0..left.num_rows() {
+            let l = ScalarValue::try_from_array(l_col, row)?;
+            let r = ScalarValue::try_from_array(r_col, row)?;
+            let equal = match (&l, &r) {
+                (ScalarValue::Float64(Some(a)), ScalarValue::Float64(Some(b))) => {
+                    tolerance.float_eq(*a, *b)
+                }
+                (ScalarValue::Float32(Some(a)), ScalarValue::Float32(Some(b))) => {
+                    tolerance.float_eq(*a as f64, *b as f64)
+                }
+                _ => l == r,
+            };
+            if !equal {
+                report.mismatches.push(Mismatch {
+                    row,
+                    column: field.name().clone(),
+                    left: l.to_string(),
+                    right: r.to_string(),
+                });
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::{ArrayRef, Float64Array, Int64Array};
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use std::sync::Arc;
+
+    const TOLERANCE: Tolerance = Tolerance {
+        abs: 1e-9,
+        rel: 1e-9,
+    };
+
+    fn batch(ids: Vec<i64>, values: Vec<Option<f64>>) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("value", DataType::Float64, true),
+        ]));
+        RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int64Array::from(ids)) as ArrayRef,
+                Arc::new(Float64Array::from(values)),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn float_eq_handles_special_values() {
+        assert!(TOLERANCE.float_eq(f64::INFINITY, f64::INFINITY));
+        assert!(TOLERANCE.float_eq(f64::NEG_INFINITY, f64::NEG_INFINITY));
+        assert!(TOLERANCE.float_eq(f64::NAN, f64::NAN));
+        assert!(!TOLERANCE.float_eq(f64::INFINITY, f64::NEG_INFINITY));
+        assert!(!TOLERANCE.float_eq(f64::INFINITY, 1e300));
+        assert!(!TOLERANCE.float_eq(f64::NAN, 0.0));
+    }
+
+    #[test]
+    fn float_eq_uses_absolute_or_relative_tolerance() {
+        assert!(TOLERANCE.float_eq(0.0, 1e-10));
+        assert!(TOLERANCE.float_eq(1e12, 1e12 + 1e2));
+        assert!(!TOLERANCE.float_eq(1.0, 1.0 + 1e-6));
+    }
+
+    #[test]
+    fn no_batches_equal_empty_batches() {
+        let empty = batch(vec![], vec![]);
+        let report = compare_batches("t", &[], &[empty.clone(), empty], TOLERANCE).unwrap();
+        assert!(report.is_ok(), "{}", report);
+
+        let report = compare_batches("t", &[], &[batch(vec![1], vec![None])], TOLERANCE).unwrap();
+        assert_eq!(
+            report.shape_errors,
+            ["row count differs: DataFrame=0 SQL=1"]
+        );
+    }
+
+    #[test]
+    fn compares_across_batch_boundaries() {
+        let left = [batch(vec![1, 2], vec![Some(0.5), None])];
+        let right = [
+            batch(vec![1], vec![Some(0.5 + 1e-12)]),
+            batch(vec![2], vec![None]),
+        ];
+        let report = compare_batches("t", &left, &right, TOLERANCE).unwrap();
+        assert!(report.is_ok(), "{}", report);
+        assert_eq!(report.rows, 2);
+    }
+
+    #[test]
+    fn reports_mismatched_cells() {
+        let left = [batch(vec![1, 2], vec![Some(1.0), Some(f64::INFINITY)])];
+        let right = [batch(vec![1, 3], vec![Some(1.0), Some(f64::INFINITY)])];
+        let report = compare_batches("t", &left, &right, TOLERANCE).unwrap();
+        assert_eq!(report.mismatches.len(), 1);
+        assert_eq!(report.mismatches[0].row, 1);
+        assert_eq!(report.mismatches[0].column, "id");
+    }
+}
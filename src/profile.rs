@@ -0,0 +1,90 @@
+//! Timings of the `profile` command: each aggregation's DataFrame API and SQL plans are
+//! executed without printing their results.
+
+use crate::aggregations::{Aggregation, AggregationInput};
+use crate::output::OutputSink;
+use anyhow::Result;
+use datafusion::arrow::array::{ArrayRef, Float64Array, Int64Array, RecordBatch, StringArray};
+use datafusion::arrow::datatypes::{DataType, Field, Schema};
+use datafusion::prelude::*;
+use log::info;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Execute `df` and count its rows
+async fn collect_rows(df: DataFrame) -> Result<i64> {
+    let batches = df.collect().await?;
+    Ok(batches.iter().map(|b| b.num_rows() as i64).sum())
+}
+
+fn elapsed_ms(started: Instant) -> f64 {
+    started.elapsed().as_secs_f64() * 1000.0
+}
+
+/// Time both implementations of every aggregation and emit one `profile` row per
+/// aggregation (`rows`, `dataframe_ms`, `sql_ms`).
+///
+/// With `explain`, the `EXPLAIN ANALYZE` output of each SQL implementation (per-operator
+/// rows and elapsed compute time) is emitted as `profile_<name>`.
+pub async fn profile(
+    aggregations: &[Box<dyn Aggregation>],
+    input: &AggregationInput,
+    sink: &OutputSink,
+    explain: bool,
+) -> Result<()> {
+    let mut names = Vec::new();
+    let mut rows = Vec::new();
+    let mut dataframe_ms = Vec::new();
+    let mut sql_ms = Vec::new();
+
+    for agg in aggregations {
+        let sql = agg.sql(input);
+        // Planning is part of the timings
+        let started = Instant::now();
+        let df_rows = collect_rows(agg.sorted_dataframe(input)?).await?;
+        let df_time = elapsed_ms(started);
+
+        let started = Instant::now();
+        collect_rows(input.ctx.sql(&sql).await?.sort(agg.sort_order())?).await?;
+        let sql_time = elapsed_ms(started);
+        info!(
+            "{:<36} {:>8} rows  DataFrame {:>9.1} ms  SQL {:>9.1} ms",
+            agg.name(),
+            df_rows,
+            df_time,
+            sql_time
+        );
+
+        names.push(agg.name());
+        rows.push(df_rows);
+        dataframe_ms.push(df_time);
+        sql_ms.push(sql_time);
+
+        if explain {
+            let plan = input.ctx.sql(&format!("EXPLAIN ANALYZE {}", sql)).await?;
+            sink.emit(
+                &format!("profile_{}", agg.name()),
+                &format!("{} (EXPLAIN ANALYZE)", agg.name()),
+                plan,
+            )
+            .await?;
+        }
+    }
+
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("aggregation", DataType::Utf8, false),
+        Field::new("rows", DataType::Int64, false),
+        Field::new("dataframe_ms", DataType::Float64, false),
+        Field::new("sql_ms", DataType::Float64, false),
+    ]));
+    let batch = RecordBatch::try_new(
+        schema.clone(),
+        vec![
+            Arc::new(StringArray::from(names)) as ArrayRef,
+            Arc::new(Int64Array::from(rows)),
+            Arc::new(Float64Array::from(dataframe_ms)),
+            Arc::new(Float64Array::from(sql_ms)),
+        ],
+    )?;
+    sink.write("profile", "Aggregation timings", schema, &[batch])
+}
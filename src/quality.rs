@@ -0,0 +1,241 @@
+use crate::dataset::{Dataset, Source, CANONICAL_COLUMNS};
+use crate::output::OutputSink;
+use crate::window::TimeWindow;
+use anyhow::{anyhow, Result};
+use datafusion::arrow::array::{Array, Float64Array, Int64Array, RecordBatch, StringArray};
+use datafusion::arrow::datatypes::{DataType, Field, Schema};
+use datafusion::functions::datetime::expr_fn::date_trunc;
+use datafusion::functions_aggregate::expr_fn::{count, sum};
+use datafusion::prelude::*;
+use std::sync::Arc;
+
+/// Monetary columns that should never be negative
+const AMOUNT_COLUMNS: &[&str] = &[
+    "fare_amount",
+    "extra",
+    "mta_tax",
+    "tip_amount",
+    "tolls_amount",
+    "improvement_surcharge",
+    "total_amount",
+    "congestion_surcharge",
+    "airport_fee",
+    "cbd_congestion_fee",
+];
+
+/// Fastest plausible average speed of a taxi trip
+const MAX_SPEED_MPH: f64 = 100.0;
+
+/// Taxi zones 1..=263 are real zones; 264/265 are the lookup's "Unknown"/"Outside of NYC"
+const MAX_KNOWN_LOCATION_ID: i64 = 263;
+
+/// One data quality check: rows matching `predicate` violate it
+pub struct Rule {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub predicate: Expr,
+}
+
+/// The checks run by the `quality` command, in report order
+pub fn rules(window: &TimeWindow) -> Vec<Rule> {
+    let outside_known_zones = |c: &str| col(c).not_between(lit(1i64), lit(MAX_KNOWN_LOCATION_ID));
+    let not_in =
+        |c: &str, codes: &[i64]| col(c).in_list(codes.iter().map(|&v| lit(v)).collect(), true);
+
+    vec![
+        Rule {
+            name: "negative_amount",
+            description: "Any fare, fee, tip or total below zero",
+            predicate: AMOUNT_COLUMNS
+                .iter()
+                .map(|c| col(*c).lt(lit(0.0)))
+                .reduce(Expr::or)
+                .expect("AMOUNT_COLUMNS is not empty"),
+        },
+        Rule {
+            name: "zero_distance",
+            description: "trip_distance is zero or negative",
+            predicate: col("trip_distance").lt_eq(lit(0.0)),
+        },
+        Rule {
+            name: "dropoff_before_pickup",
+            description: "dropoff_datetime earlier than pickup_datetime",
+            predicate: col("dropoff_datetime").lt(col("pickup_datetime")),
+        },
+        Rule {
+            name: "implausible_speed",
+            description: "Average speed above 100 mph",
+            predicate: col("avg_speed_mph").gt(lit(MAX_SPEED_MPH)),
+        },
+        Rule {
+            name: "unknown_location",
+            description: "Pickup or dropoff location outside taxi zones 1-263",
+            predicate: outside_known_zones("pu_location_id")
+                .or(outside_known_zones("do_location_id")),
+        },
+        Rule {
+            name: "bad_vendor_id",
+            description: "vendor_id not in 1, 2, 6, 7",
+            predicate: not_in("vendor_id", &[1, 2, 6, 7]),
+        },
+        Rule {
+            name: "bad_ratecode_id",
+            description: "RatecodeID not in 1-6 or 99",
+            predicate: not_in("ratecode_id", &[1, 2, 3, 4, 5, 6, 99]),
+        },
+        Rule {
+            name: "bad_payment_type",
+            description: "payment_type not in 1-6 (e.g. 0)",
+            predicate: not_in("payment_type", &[1, 2, 3, 4, 5, 6]),
+        },
+        Rule {
+            name: "bad_store_and_fwd_flag",
+            description: "store_and_fwd_flag not Y or N",
+            predicate: col("store_and_fwd_flag").in_list(vec![lit("Y"), lit("N")], true),
+        },
+        Rule {
+            name: "pickup_outside_window",
+            description: "Pickup outside the analyzed time window",
+            predicate: window.contains().is_not_true(),
+        },
+    ]
+}
+
+/// Canonical columns that `dataset` actually provides (always-NULL columns are not checked)
+fn provided_columns(dataset: Dataset) -> Vec<&'static str> {
+    CANONICAL_COLUMNS
+        .iter()
+        .map(|(name, _)| *name)
+        .filter(|name| !matches!(dataset.source(name), Source::Null))
+        .collect()
+}
+
+/// Scan the whole trip table (the time window is checked, not applied) and emit:
+/// - `quality_nulls`: per pickup month, NULL count of every provided column
+/// - `quality_rules`: per pickup month, rows violating each [`Rule`]
+/// - `quality_summary`: violations per rule over all months
+/// - `quality_sample_<rule>`: up to `samples` violating rows per rule
+pub async fn report(
+    trips: DataFrame,
+    dataset: Dataset,
+    window: &TimeWindow,
+    sink: &OutputSink,
+    samples: usize,
+) -> Result<()> {
+    let pickup_month = date_trunc(lit("month"), col("pickup_datetime")).alias("pickup_month");
+    let month_order = vec![col("pickup_month").sort(true, true)];
+
+    let mut null_counts = vec![count(lit(1)).alias("rows")];
+    for c in provided_columns(dataset) {
+        null_counts.push(flag_count(col(c).is_null())?.alias(c));
+    }
+    let nulls = trips
+        .clone()
+        .aggregate(vec![pickup_month.clone()], null_counts)?
+        .sort(month_order.clone())?;
+    sink.emit("quality_nulls", "NULL values by pickup month", nulls)
+        .await?;
+
+    let rules = rules(window);
+    let mut rule_counts = vec![count(lit(1)).alias("rows")];
+    for rule in &rules {
+        rule_counts.push(flag_count(rule.predicate.clone())?.alias(rule.name));
+    }
+    let by_month = trips
+        .clone()
+        .aggregate(vec![pickup_month], rule_counts)?
+        .sort(month_order)?
+        .cache()
+        .await?;
+    sink.emit(
+        "quality_rules",
+        "Rule violations by pickup month",
+        by_month.clone(),
+    )
+    .await?;
+
+    // Totals over all months, reshaped to one row per rule
+    let mut totals = vec![sum(col("rows")).alias("rows")];
+    totals.extend(rules.iter().map(|r| sum(col(r.name)).alias(r.name)));
+    let totals = by_month.aggregate(vec![], totals)?.collect().await?;
+    let total = |name: &str| total_value(&totals, name);
+    let rows = total("rows")?;
+    let violations = rules
+        .iter()
+        .map(|r| total(r.name))
+        .collect::<Result<Vec<_>>>()?;
+    let summary = summary_batch(&rules, rows, &violations)?;
+    sink.write(
+        "quality_summary",
+        "Data quality summary",
+        summary.schema(),
+        &[summary],
+    )?;
+
+    if samples == 0 {
+        return Ok(());
+    }
+    // Rules without violations have nothing to show
+    for (rule, _) in rules.iter().zip(&violations).filter(|(_, &v)| v > 0) {
+        let sample = trips
+            .clone()
+            .filter(rule.predicate.clone())?
+            .limit(0, Some(samples))?;
+        sink.emit(
+            &format!("quality_sample_{}", rule.name),
+            &format!("Sample rows: {} ({})", rule.name, rule.description),
+            sample,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// `SUM(CASE WHEN predicate THEN 1 ELSE 0 END)`: rows where `predicate` is true
+fn flag_count(predicate: Expr) -> Result<Expr> {
+    Ok(sum(when(predicate, lit(1i64)).otherwise(lit(0i64))?))
+}
+
+/// Single value of the one-row totals aggregate (NULL, i.e. no rows, counts as 0)
+fn total_value(totals: &[RecordBatch], name: &str) -> Result<i64> {
+    let column = totals
+        .iter()
+        .find(|b| b.num_rows() > 0)
+        .and_then(|b| b.column_by_name(name))
+        .and_then(|c| c.as_any().downcast_ref::<Int64Array>())
+        .ok_or_else(|| anyhow!("Missing quality total {}", name))?;
+    Ok(if column.is_null(0) {
+        0
+    } else {
+        column.value(0)
+    })
+}
+
+/// One row per rule with its violation count and share of all rows
+fn summary_batch(rules: &[Rule], rows: i64, violations: &[i64]) -> Result<RecordBatch> {
+    let pct: Vec<f64> = violations
+        .iter()
+        .map(|&v| match rows {
+            0 => 0.0,
+            _ => 100.0 * v as f64 / rows as f64,
+        })
+        .collect();
+
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("rule", DataType::Utf8, false),
+        Field::new("description", DataType::Utf8, false),
+        Field::new("violating_rows", DataType::Int64, false),
+        Field::new("pct_of_rows", DataType::Float64, false),
+    ]));
+    Ok(RecordBatch::try_new(
+        schema,
+        vec![
+            Arc::new(StringArray::from_iter_values(rules.iter().map(|r| r.name))),
+            Arc::new(StringArray::from_iter_values(
+                rules.iter().map(|r| r.description),
+            )),
+            Arc::new(Int64Array::from(violations.to_vec())),
+            Arc::new(Float64Array::from(pct)),
+        ],
+    )?)
+}
@@ -0,0 +1,137 @@
+use crate::output::OutputSink;
+use crate::schema::{describe_table, table_names};
+use anyhow::{anyhow, Result};
+use datafusion::arrow::array::{RecordBatch, StringArray};
+use datafusion::arrow::datatypes::{DataType, Field, Schema};
+use datafusion::arrow::util::pretty::pretty_format_batches;
+use datafusion::prelude::*;
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+
+const HELP: &str = r#"Enter SQL statements terminated by ';' (they may span several lines).
+Meta commands:
+  \d          list registered tables
+  \d <table>  show the columns of a table
+  \timing     toggle query timing
+  \?          show this help
+  \q          quit (or Ctrl-D)"#;
+
+/// Default history file: `~/.nyc_tlc_datafusion_history`
+fn default_history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".nyc_tlc_datafusion_history"))
+}
+
+/// Interactive SQL shell over the tables registered in `ctx`.
+///
+/// Results are emitted through `sink`, so `--output-format`/`--output-dir` apply
+/// like for the aggregations (each result is named `query_<n>`).
+pub async fn run(ctx: &SessionContext, sink: &OutputSink, history: Option<PathBuf>) -> Result<()> {
+    let mut editor = DefaultEditor::new()?;
+    let history = history.or_else(default_history_path);
+    if let Some(path) = &history {
+        // A missing history file is normal on first use
+        let _ = editor.load_history(path);
+    }
+
+    println!("{}\n", HELP);
+
+    let mut buffer = String::new();
+    let mut timing = false;
+    let mut query_count = 0;
+
+    loop {
+        let prompt = if buffer.is_empty() { "tlc> " } else { " ..> " };
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            // Ctrl-C discards the statement being typed
+            Err(ReadlineError::Interrupted) => {
+                buffer.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+        let trimmed = line.trim();
+
+        if buffer.is_empty() && trimmed.starts_with('\\') {
+            editor.add_history_entry(trimmed)?;
+            match trimmed {
+                "\\q" => break,
+                "\\?" => println!("{}", HELP),
+                "\\timing" => {
+                    timing = !timing;
+                    println!("Timing is {}.", if timing { "on" } else { "off" });
+                }
+                _ => {
+                    if let Err(e) = describe(ctx, trimmed).await {
+                        eprintln!("Error: {}", e);
+                    }
+                }
+            }
+            continue;
+        }
+
+        if trimmed.is_empty() && buffer.is_empty() {
+            continue;
+        }
+        buffer.push_str(&line);
+        buffer.push('\n');
+        if !trimmed.ends_with(';') {
+            continue;
+        }
+
+        let sql = std::mem::take(&mut buffer);
+        editor.add_history_entry(sql.trim())?;
+        query_count += 1;
+
+        let started = Instant::now();
+        match execute(ctx, sink, &sql, query_count).await {
+            Ok(rows) if timing => println!(
+                "{} rows, time: {:.3}s",
+                rows,
+                started.elapsed().as_secs_f64()
+            ),
+            Ok(_) => {}
+            Err(e) => eprintln!("Error: {}", e),
+        }
+    }
+
+    if let Some(path) = &history {
+        editor.save_history(path)?;
+    }
+    Ok(())
+}
+
+async fn execute(ctx: &SessionContext, sink: &OutputSink, sql: &str, n: usize) -> Result<usize> {
+    let df = ctx.sql(sql).await?;
+    let batches = sink
+        .emit(&format!("query_{}", n), &format!("query {}", n), df)
+        .await?;
+    Ok(batches.iter().map(|b| b.num_rows()).sum())
+}
+
+/// `\d` (list tables) and `\d <table>` (show columns)
+async fn describe(ctx: &SessionContext, command: &str) -> Result<()> {
+    let mut parts = command.split_whitespace();
+    if parts.next() != Some("\\d") {
+        return Err(anyhow!("Unknown command {}, type \\? for help", command));
+    }
+
+    let batch = match parts.next() {
+        None => {
+            let tables = table_names(ctx);
+            let schema = Arc::new(Schema::new(vec![Field::new(
+                "table",
+                DataType::Utf8,
+                false,
+            )]));
+            RecordBatch::try_new(schema, vec![Arc::new(StringArray::from(tables))])?
+        }
+        Some(table) => describe_table(ctx, table).await?,
+    };
+    println!("{}", pretty_format_batches(&[batch])?);
+    Ok(())
+}
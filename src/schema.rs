@@ -0,0 +1,272 @@
+use crate::dataset::{Dataset, Source, CANONICAL_COLUMNS};
+use anyhow::{anyhow, Context, Result};
+use datafusion::arrow::array::{BooleanArray, RecordBatch, StringArray};
+use datafusion::arrow::datatypes::{DataType, Field, Schema};
+use datafusion::common::ScalarValue;
+use datafusion::functions::core::expr_fn::coalesce;
+use datafusion::functions::datetime::expr_fn::date_part;
+use datafusion::parquet::arrow::arrow_reader::{ArrowReaderMetadata, ArrowReaderOptions};
+use datafusion::prelude::*;
+use log::info;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Files sharing one physical schema (one TLC "vintage")
+struct SchemaGroup {
+    schema: Schema,
+    files: Vec<PathBuf>,
+}
+
+/// Find a raw column by name, preferring an exact match over a case-insensitive one
+/// (TLC renamed e.g. `airport_fee` to `Airport_fee` between vintages).
+pub(crate) fn resolve<'a>(schema: &'a Schema, raw: &str) -> Option<&'a Field> {
+    schema
+        .fields()
+        .iter()
+        .find(|f| f.name() == raw)
+        .or_else(|| {
+            schema
+                .fields()
+                .iter()
+                .find(|f| f.name().eq_ignore_ascii_case(raw))
+        })
+        .map(|f| f.as_ref())
+}
+
+/// Types that only differ in physical representation (DataFusion reads strings as views)
+pub(crate) fn same_logical_type(a: &DataType, b: &DataType) -> bool {
+    matches!(
+        (a, b),
+        (
+            DataType::Utf8View | DataType::Utf8,
+            DataType::Utf8View | DataType::Utf8
+        )
+    ) || a == b
+}
+
+/// Projection from one vintage's raw columns to the canonical schema.
+///
+/// Returns the expressions plus human-readable notes about how the vintage drifts
+/// from the canonical schema (missing, renamed and retyped columns).
+fn canonical_projection(dataset: Dataset, schema: &Schema) -> (Vec<Expr>, Vec<String>) {
+    let mut notes = Vec::new();
+
+    let exprs = CANONICAL_COLUMNS
+        .iter()
+        .map(|(name, data_type)| {
+            let expr = match dataset.source(name) {
+                Source::Column(raw) => match resolve(schema, raw) {
+                    Some(field) => {
+                        if field.name() != raw {
+                            notes.push(format!("{} read from '{}'", name, field.name()));
+                        }
+                        if !same_logical_type(field.data_type(), data_type) {
+                            notes.push(format!(
+                                "{} cast {} -> {}",
+                                name,
+                                field.data_type(),
+                                data_type
+                            ));
+                        }
+                        ident(field.name())
+                    }
+                    None => {
+                        notes.push(format!("{} missing (NULL)", name));
+                        lit(ScalarValue::Null)
+                    }
+                },
+                Source::Sum(raws) => {
+                    let present: Vec<Expr> = raws
+                        .iter()
+                        .filter_map(|raw| resolve(schema, raw))
+                        .map(|field| coalesce(vec![ident(field.name()), lit(0.0)]))
+                        .collect();
+                    if present.len() < raws.len() {
+                        notes.push(format!(
+                            "{} built from {} of {} components",
+                            name,
+                            present.len(),
+                            raws.len()
+                        ));
+                    }
+                    present
+                        .into_iter()
+                        .reduce(|acc, e| acc + e)
+                        .unwrap_or(lit(ScalarValue::Null))
+                }
+                Source::Null => lit(ScalarValue::Null),
+            };
+            cast(expr, data_type.clone()).alias(*name)
+        })
+        .collect();
+
+    (exprs, notes)
+}
+
+/// Append the derived columns to a canonical trip table:
+/// - `trip_duration_minutes`: dropoff minus pickup, negative when the dropoff comes first
+/// - `avg_speed_mph`: `trip_distance` per hour of duration, NULL unless the duration is positive
+pub fn with_derived_columns(df: DataFrame) -> Result<DataFrame> {
+    let duration = (date_part(lit("epoch"), col("dropoff_datetime"))
+        - date_part(lit("epoch"), col("pickup_datetime")))
+        / lit(60.0);
+    let speed = when(
+        col("trip_duration_minutes").gt(lit(0.0)),
+        col("trip_distance") / (col("trip_duration_minutes") / lit(60.0)),
+    )
+    .end()?;
+
+    Ok(df
+        .with_column("trip_duration_minutes", duration)?
+        .with_column("avg_speed_mph", speed)?)
+}
+
+/// Group files by their Parquet footer schema, keeping the input order within each group
+fn group_by_schema(files: &[PathBuf]) -> Result<Vec<SchemaGroup>> {
+    let mut groups: Vec<SchemaGroup> = Vec::new();
+    for path in files {
+        let schema = read_arrow_schema(path)?;
+        match groups
+            .iter_mut()
+            .find(|g| g.schema.fields() == schema.fields())
+        {
+            Some(group) => group.files.push(path.clone()),
+            None => groups.push(SchemaGroup {
+                schema,
+                files: vec![path.clone()],
+            }),
+        }
+    }
+    Ok(groups)
+}
+
+/// Arrow schema stored in a Parquet file footer (no data pages are read)
+pub fn read_arrow_schema(path: &Path) -> Result<Schema> {
+    let file = File::open(path).with_context(|| format!("Cannot open {}", path.display()))?;
+    let metadata = ArrowReaderMetadata::load(&file, ArrowReaderOptions::default())
+        .with_context(|| format!("Cannot read Parquet footer of {}", path.display()))?;
+    Ok(metadata.schema().as_ref().clone())
+}
+
+/// Register `files` as the canonical `table` (normally `<dataset>`), even when they come
+/// from different TLC vintages.
+///
+/// Files are grouped by schema, each group is projected onto the canonical columns
+/// (names resolved case-insensitively, types cast, missing columns filled with NULL)
+/// and the groups are combined with `UNION ALL`; the derived columns of
+/// [`with_derived_columns`] are appended. The raw `<table>_raw` table is
+/// only registered when all files share the same schema.
+pub async fn register_merged(
+    ctx: &SessionContext,
+    dataset: Dataset,
+    table: &str,
+    files: &[PathBuf],
+) -> Result<()> {
+    if files.is_empty() {
+        return Err(anyhow!("No {} files to register as '{}'", dataset, table));
+    }
+
+    let raw_table = format!("{}_raw", table);
+    let groups = group_by_schema(files)?;
+    let mut merged: Option<DataFrame> = None;
+
+    for (i, group) in groups.iter().enumerate() {
+        let paths: Vec<String> = group
+            .files
+            .iter()
+            .map(|p| p.to_string_lossy().into_owned())
+            .collect();
+        let raw = ctx
+            .read_parquet(paths, ParquetReadOptions::default())
+            .await
+            .context("register_parquet failed")?;
+
+        let (projection, notes) = canonical_projection(dataset, raw.schema().as_arrow());
+        if groups.len() > 1 {
+            info!(
+                "Schema variant {} ({} files, first: {}): {}",
+                i + 1,
+                group.files.len(),
+                group.files[0].display(),
+                if notes.is_empty() {
+                    "matches canonical schema".to_string()
+                } else {
+                    notes.join(", ")
+                }
+            );
+        }
+
+        if groups.len() == 1 {
+            ctx.register_table(raw_table.as_str(), raw.clone().into_view())?;
+        }
+
+        let canonical = raw.select(projection).with_context(|| {
+            format!(
+                "Cannot map {} to the canonical {} schema",
+                group.files[0].display(),
+                dataset
+            )
+        })?;
+
+        merged = Some(match merged {
+            Some(df) => df.union(canonical)?,
+            None => canonical,
+        });
+    }
+
+    if groups.len() > 1 {
+        info!(
+            "Note: '{}' not registered, raw schemas differ across files\n",
+            raw_table
+        );
+    }
+
+    let merged = with_derived_columns(merged.expect("at least one schema group"))?;
+    ctx.register_table(table, merged.into_view())?;
+    Ok(())
+}
+
+/// Names of every table registered in `ctx`, sorted
+pub fn table_names(ctx: &SessionContext) -> Vec<String> {
+    let mut tables: Vec<String> = ctx
+        .catalog_names()
+        .into_iter()
+        .filter_map(|c| ctx.catalog(&c))
+        .flat_map(|catalog| {
+            catalog
+                .schema_names()
+                .into_iter()
+                .filter_map(move |s| catalog.schema(&s))
+                .flat_map(|schema| schema.table_names())
+        })
+        .collect();
+    tables.sort();
+    tables
+}
+
+/// `(column, type, nullable)` rows describing a registered table
+pub async fn describe_table(ctx: &SessionContext, table: &str) -> Result<RecordBatch> {
+    let df = ctx.table(table).await?;
+    let fields = df.schema().fields();
+
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("column", DataType::Utf8, false),
+        Field::new("type", DataType::Utf8, false),
+        Field::new("nullable", DataType::Boolean, false),
+    ]));
+    Ok(RecordBatch::try_new(
+        schema,
+        vec![
+            Arc::new(StringArray::from_iter_values(
+                fields.iter().map(|f| f.name().clone()),
+            )),
+            Arc::new(StringArray::from_iter_values(
+                fields.iter().map(|f| f.data_type().to_string()),
+            )),
+            Arc::new(BooleanArray::from_iter(
+                fields.iter().map(|f| Some(f.is_nullable())),
+            )),
+        ],
+    )?)
+}
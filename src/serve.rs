@@ -0,0 +1,363 @@
+//! Minimal HTTP/1.1 server of the `serve` command (one request per connection, no TLS,
+//! meant for a trusted network or behind a reverse proxy).
+//!
+//! - `GET /health`
+//! - `GET /tables`: registered table names
+//! - `GET /aggregations`: name and description of every aggregation
+//! - `GET /aggregations/<name>`: DataFrame API result of one aggregation
+//! - `POST /query`: result of the read-only SQL query in the request body (DDL, DML such as
+//!   `COPY` and statements such as `SET` are refused)
+//!
+//! Results are JSON arrays of row objects; `?format=csv` or `?format=ndjson` switches format.
+//! Results are built in memory, so one with more than `max_rows` rows is refused.
+
+use crate::aggregations::{self, AggregationInput};
+use crate::output::{write_batches, OutputFormat};
+use crate::schema::table_names;
+use anyhow::{anyhow, Context, Result};
+use datafusion::arrow::array::{RecordBatch, StringArray};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use datafusion::execution::context::SQLOptions;
+use datafusion::prelude::*;
+use log::{info, warn};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Largest accepted request head (request line and headers)
+const MAX_HEAD_BYTES: usize = 64 * 1024;
+
+/// Largest accepted request body (SQL text)
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+
+struct Request {
+    method: String,
+    path: String,
+    query: String,
+    body: String,
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn text(status: &'static str, body: impl Into<String>) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: format!("{}\n", body.into()).into_bytes(),
+        }
+    }
+}
+
+/// Serve `input`'s session on `addr` until the process is stopped, answering with at most
+/// `max_rows` rows
+pub async fn serve(addr: SocketAddr, input: AggregationInput, max_rows: usize) -> Result<()> {
+    let listener = TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Cannot listen on {}", addr))?;
+    info!("Listening on http://{}", listener.local_addr()?);
+
+    let input = Arc::new(input);
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let input = input.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, &input, max_rows).await {
+                warn!("{}: {:#}", peer, e);
+            }
+        });
+    }
+}
+
+async fn handle_connection(
+    mut stream: TcpStream,
+    input: &AggregationInput,
+    max_rows: usize,
+) -> Result<()> {
+    let response = match read_request(&mut stream).await {
+        Ok(request) => {
+            info!("{} {}", request.method, request.path);
+            route(&request, input, max_rows)
+                .await
+                .unwrap_or_else(|e| Response::text("500 Internal Server Error", format!("{:#}", e)))
+        }
+        Err(e) => Response::text("400 Bad Request", format!("{:#}", e)),
+    };
+
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&response.body).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+async fn read_request<R: AsyncRead + Unpin>(stream: &mut R) -> Result<Request> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let head_end = loop {
+        if let Some(i) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            break i;
+        }
+        if buffer.len() > MAX_HEAD_BYTES {
+            return Err(anyhow!("Request head too large"));
+        }
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(anyhow!(
+                "Connection closed before the end of the request head"
+            ));
+        }
+        buffer.extend_from_slice(&chunk[..n]);
+    };
+
+    let head = String::from_utf8_lossy(&buffer[..head_end]).into_owned();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_string();
+    let target = request_line.next().unwrap_or_default();
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    let content_length = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .map(|(_, value)| value.trim().parse::<usize>())
+        .transpose()
+        .context("Invalid Content-Length")?
+        .unwrap_or(0);
+    if content_length > MAX_BODY_BYTES {
+        return Err(anyhow!("Request body too large"));
+    }
+
+    let mut body = buffer[head_end + 4..].to_vec();
+    while body.len() < content_length {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(anyhow!(
+                "Connection closed before the end of the request body"
+            ));
+        }
+        body.extend_from_slice(&chunk[..n]);
+    }
+    body.truncate(content_length);
+
+    Ok(Request {
+        method,
+        path: path.to_string(),
+        query: query.to_string(),
+        body: String::from_utf8(body).context("Request body is not UTF-8")?,
+    })
+}
+
+async fn route(request: &Request, input: &AggregationInput, max_rows: usize) -> Result<Response> {
+    let format = match query_param(&request.query, "format") {
+        None | Some("json") => OutputFormat::Json,
+        Some("csv") => OutputFormat::Csv,
+        Some("ndjson") => OutputFormat::Ndjson,
+        Some(other) => {
+            return Ok(Response::text(
+                "400 Bad Request",
+                format!("Unsupported format '{}', use json, csv or ndjson", other),
+            ))
+        }
+    };
+
+    let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+    match (request.method.as_str(), segments.as_slice()) {
+        ("GET", ["health"]) => Ok(Response::text("200 OK", "ok")),
+        ("GET", ["tables"]) => {
+            let tables = table_names(&input.ctx);
+            let schema = Arc::new(Schema::new(vec![Field::new(
+                "table",
+                DataType::Utf8,
+                false,
+            )]));
+            let batch =
+                RecordBatch::try_new(schema.clone(), vec![Arc::new(StringArray::from(tables))])?;
+            encode(format, schema, &[batch])
+        }
+        ("GET", ["aggregations"]) => {
+            let registry = aggregations::registry();
+            let schema = Arc::new(Schema::new(vec![
+                Field::new("name", DataType::Utf8, false),
+                Field::new("description", DataType::Utf8, false),
+            ]));
+            let batch = RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(StringArray::from_iter_values(
+                        registry.iter().map(|a| a.name()),
+                    )),
+                    Arc::new(StringArray::from_iter_values(
+                        registry.iter().map(|a| a.description()),
+                    )),
+                ],
+            )?;
+            encode(format, schema, &[batch])
+        }
+        ("GET", ["aggregations", name]) => {
+            let Some(agg) = aggregations::registry()
+                .into_iter()
+                .find(|a| a.name() == *name)
+            else {
+                return Ok(Response::text(
+                    "404 Not Found",
+                    format!("Unknown aggregation '{}'", name),
+                ));
+            };
+            if agg.requires_zones() && input.zones.is_none() {
+                return Ok(Response::text(
+                    "409 Conflict",
+                    format!("{} needs the taxi zone lookup (--zones)", name),
+                ));
+            }
+            respond(format, agg.labeled_dataframe(input)?, max_rows).await
+        }
+        ("POST", ["query"]) => {
+            // CREATE EXTERNAL TABLE could read any file and COPY write one
+            let options = SQLOptions::new()
+                .with_allow_ddl(false)
+                .with_allow_dml(false)
+                .with_allow_statements(false);
+            match input.ctx.sql_with_options(&request.body, options).await {
+                Ok(df) => respond(format, df, max_rows).await,
+                Err(e) => Ok(Response::text("400 Bad Request", e.to_string())),
+            }
+        }
+        _ => Ok(Response::text(
+            "404 Not Found",
+            format!("No route for {} {}", request.method, request.path),
+        )),
+    }
+}
+
+/// Value of `name` in a `a=1&b=2` query string (not percent-decoded)
+fn query_param<'a>(query: &'a str, name: &str) -> Option<&'a str> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+/// Encode the result of `df`, or refuse it when it has more than `max_rows` rows
+async fn respond(format: OutputFormat, df: DataFrame, max_rows: usize) -> Result<Response> {
+    let schema: SchemaRef = Arc::new(df.schema().as_arrow().clone());
+    // One extra row tells a result at the cap from a larger one
+    let batches = df.limit(0, Some(max_rows + 1))?.collect().await?;
+    if batches.iter().map(|b| b.num_rows()).sum::<usize>() > max_rows {
+        return Ok(Response::text(
+            "413 Content Too Large",
+            format!(
+                "The result has more than {} rows, add a LIMIT or raise --max-rows",
+                max_rows
+            ),
+        ));
+    }
+    encode(format, schema, &batches)
+}
+
+fn encode(format: OutputFormat, schema: SchemaRef, batches: &[RecordBatch]) -> Result<Response> {
+    let mut body = Vec::new();
+    write_batches(format, &mut body, schema, batches)?;
+    let content_type = match format {
+        OutputFormat::Csv => "text/csv; charset=utf-8",
+        OutputFormat::Ndjson => "application/x-ndjson",
+        _ => "application/json",
+    };
+    Ok(Response {
+        status: "200 OK",
+        content_type,
+        body,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn parse(raw: &[u8]) -> Result<Request> {
+        read_request(&mut &raw[..]).await
+    }
+
+    #[test]
+    fn finds_query_parameters() {
+        assert_eq!(query_param("format=csv", "format"), Some("csv"));
+        assert_eq!(
+            query_param("a=1&format=ndjson&b=2", "format"),
+            Some("ndjson")
+        );
+        assert_eq!(query_param("format=csv&format=json", "format"), Some("csv"));
+        assert_eq!(query_param("flag&format=", "format"), Some(""));
+        assert_eq!(query_param("formats=csv", "format"), None);
+        assert_eq!(query_param("", "format"), None);
+    }
+
+    #[tokio::test]
+    async fn reads_request_line_and_body() {
+        let request = parse(
+            b"POST /query?format=csv HTTP/1.1\r\nHost: x\r\ncontent-length: 8\r\n\r\nSELECT 1",
+        )
+        .await
+        .unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/query");
+        assert_eq!(request.query, "format=csv");
+        assert_eq!(request.body, "SELECT 1");
+    }
+
+    #[tokio::test]
+    async fn body_defaults_to_empty_and_stops_at_content_length() {
+        let request = parse(b"GET /health HTTP/1.1\r\n\r\n").await.unwrap();
+        assert_eq!(
+            (request.path.as_str(), request.query.as_str()),
+            ("/health", "")
+        );
+        assert_eq!(request.body, "");
+
+        let request = parse(b"POST /query HTTP/1.1\r\nContent-Length: 3\r\n\r\nabcdef")
+            .await
+            .unwrap();
+        assert_eq!(request.body, "abc");
+    }
+
+    #[tokio::test]
+    async fn reads_a_body_split_across_reads() {
+        let mut stream =
+            (&b"POST /query HTTP/1.1\r\nContent-Length: 11\r\n\r\nSELECT"[..]).chain(&b" 1, 2"[..]);
+        let request = read_request(&mut stream).await.unwrap();
+        assert_eq!(request.body, "SELECT 1, 2");
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_requests() {
+        assert!(parse(b"GET /health HTTP/1.1\r\n").await.is_err());
+        assert!(parse(b"POST /query HTTP/1.1\r\nContent-Length: x\r\n\r\n")
+            .await
+            .is_err());
+        assert!(
+            parse(b"POST /query HTTP/1.1\r\nContent-Length: 10\r\n\r\nshort")
+                .await
+                .is_err()
+        );
+        let too_large = format!(
+            "POST /query HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY_BYTES + 1
+        );
+        assert!(parse(too_large.as_bytes()).await.is_err());
+        assert!(
+            parse(b"POST /query HTTP/1.1\r\nContent-Length: 2\r\n\r\n\xff\xfe")
+                .await
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,453 @@
+//! Session builder: validates the TLC folders and registers the trip table, the code
+//! dictionaries, the calendar and the zone lookup in one `SessionContext`.
+
+use crate::aggregations::{AggregationInput, PercentileMode};
+use crate::clean::CleanDirs;
+use crate::codes;
+use crate::dataset::{check_year, Dataset, FilePattern};
+use crate::dictionary::{expected_schema, SchemaDrift};
+use crate::schema;
+use crate::window::{TimeWindow, WindowMode};
+use crate::zones::{register_zones, ZONES_TABLE};
+use anyhow::{anyhow, Context, Result};
+use chrono::NaiveDate;
+use datafusion::prelude::*;
+use log::{info, warn};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Data root used when none is given
+pub const DEFAULT_DATA_ROOT: &str = "./data";
+
+/// Where the trip data comes from and which period is analyzed.
+///
+/// Defaults to yellow taxi data of 2025 under `./data`, dropping out-of-period trips:
+///
+/// ```text
+/// let session = SessionBuilder::new(Dataset::Yellow)
+///     .data_root("/data/tlc")
+///     .years(vec![2024, 2025])
+///     .build()
+///     .await?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct SessionBuilder {
+    dataset: Dataset,
+    data_root: PathBuf,
+    data_dir: Option<PathBuf>,
+    years: Vec<i32>,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    window_mode: WindowMode,
+    zones: Option<PathBuf>,
+    cleaned: Option<CleanDirs>,
+    table: Option<String>,
+    file_pattern: Option<FilePattern>,
+    options: HashMap<String, String>,
+    strict_schema: bool,
+}
+
+/// A `SessionContext` with the TLC tables registered
+pub struct TlcSession {
+    pub ctx: SessionContext,
+    pub dataset: Dataset,
+    /// Name of the registered trip table
+    pub table: String,
+    /// Analyzed years, sorted
+    pub years: Vec<i32>,
+    pub window: TimeWindow,
+    /// Whether the taxi zone lookup was registered
+    pub has_zones: bool,
+}
+
+impl SessionBuilder {
+    pub fn new(dataset: Dataset) -> Self {
+        Self {
+            dataset,
+            data_root: PathBuf::from(DEFAULT_DATA_ROOT),
+            data_dir: None,
+            years: vec![2025],
+            from: None,
+            to: None,
+            window_mode: WindowMode::Drop,
+            zones: None,
+            cleaned: None,
+            table: None,
+            file_pattern: None,
+            options: HashMap::new(),
+            strict_schema: false,
+        }
+    }
+
+    /// Root folder holding one `<dataset>/<year>` sub-folder per year
+    pub fn data_root(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.data_root = dir.into();
+        self
+    }
+
+    /// Single folder holding the monthly files of every year (overrides the data root)
+    pub fn data_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.data_dir = Some(dir.into());
+        self
+    }
+
+    /// Years to analyze as one table
+    pub fn years(mut self, mut years: Vec<i32>) -> Self {
+        years.sort_unstable();
+        years.dedup();
+        self.years = years;
+        self
+    }
+
+    /// First pickup date to include (inclusive)
+    pub fn from(mut self, date: NaiveDate) -> Self {
+        self.from = Some(date);
+        self
+    }
+
+    /// Last pickup date to include (inclusive)
+    pub fn to(mut self, date: NaiveDate) -> Self {
+        self.to = Some(date);
+        self
+    }
+
+    pub fn window_mode(mut self, mode: WindowMode) -> Self {
+        self.window_mode = mode;
+        self
+    }
+
+    /// Taxi zone lookup CSV, required to exist (defaults to `<data-root>/taxi_zone_lookup.csv`
+    /// when present)
+    pub fn zones(mut self, path: impl Into<PathBuf>) -> Self {
+        self.zones = Some(path.into());
+        self
+    }
+
+    /// Read the output of the `clean` command instead of the raw files
+    pub fn cleaned(mut self, dirs: CleanDirs) -> Self {
+        self.cleaned = Some(dirs);
+        self
+    }
+
+    /// Name of the registered trip table (defaults to the dataset name); the raw table is
+    /// `<table>_raw`
+    pub fn table(mut self, name: impl Into<String>) -> Self {
+        self.table = Some(name.into());
+        self
+    }
+
+    /// Monthly file names to look for instead of the TLC ones
+    pub fn file_pattern(mut self, pattern: FilePattern) -> Self {
+        self.file_pattern = Some(pattern);
+        self
+    }
+
+    /// DataFusion configuration option, e.g. `datafusion.execution.target_partitions`
+    pub fn option(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.options.insert(key.into(), value.into());
+        self
+    }
+
+    /// Refuse to build the session when a file's footer is incompatible with the data
+    /// dictionary (missing required columns or changed types), instead of only warning
+    pub fn strict_schema(mut self, strict: bool) -> Self {
+        self.strict_schema = strict;
+        self
+    }
+
+    pub fn dataset(&self) -> Dataset {
+        self.dataset
+    }
+
+    /// Monthly file name pattern, the TLC one unless set
+    pub fn pattern(&self) -> FilePattern {
+        self.file_pattern
+            .clone()
+            .unwrap_or_else(|| self.dataset.file_pattern())
+    }
+
+    /// Folder holding the monthly files of `year`
+    pub fn year_dir(&self, year: i32) -> PathBuf {
+        match &self.data_dir {
+            Some(dir) => dir.clone(),
+            None => self
+                .data_root
+                .join(self.dataset.name())
+                .join(year.to_string()),
+        }
+    }
+
+    /// Validate the folder of every year and list their monthly files (nothing is read),
+    /// failing when none of them holds a file matching the pattern
+    pub fn data_files(&self) -> Result<Vec<PathBuf>> {
+        let pattern = self.pattern();
+        let mut files = Vec::new();
+        let mut searched: Vec<String> = Vec::new();
+        for &year in &self.years {
+            let data_dir = self.year_dir(year);
+            validate_data_dir(&data_dir, &pattern, year)?;
+            files.extend(pattern.find_files(&data_dir, year)?);
+            let dir = data_dir.display().to_string();
+            if !searched.contains(&dir) {
+                searched.push(dir);
+            }
+        }
+        if files.is_empty() {
+            return Err(anyhow!(
+                "No files matching '{}' found in {}",
+                pattern,
+                searched.join(", ")
+            ));
+        }
+        Ok(files)
+    }
+
+    /// DataFusion configuration with the options set, failing on unknown keys or bad values
+    pub fn session_config(&self) -> Result<SessionConfig> {
+        let mut config = SessionConfig::new();
+        for (key, value) in &self.options {
+            config
+                .options_mut()
+                .set(key, value)
+                .with_context(|| format!("Invalid session option {} = {}", key, value))?;
+        }
+        Ok(config)
+    }
+
+    /// Validate the data folders and register the trip table (and zone lookup, if found)
+    pub async fn build(self) -> Result<TlcSession> {
+        let dataset = self.dataset;
+        let table = self
+            .table
+            .clone()
+            .unwrap_or_else(|| dataset.table_name().to_string());
+        let (first_year, last_year) = match (self.years.first(), self.years.last()) {
+            (Some(first), Some(last)) => (*first, *last),
+            _ => return Err(anyhow!("No year to analyze")),
+        };
+        self.years.iter().try_for_each(|&year| check_year(year))?;
+        check_table_name(&table)?;
+
+        let window = TimeWindow::new(
+            "pickup_datetime",
+            first_year,
+            last_year,
+            self.from,
+            self.to,
+            self.window_mode,
+        )?;
+
+        let ctx = SessionContext::new_with_config(self.session_config()?);
+
+        match &self.cleaned {
+            Some(dirs) => register_cleaned(&ctx, &table, dirs).await?,
+            None => {
+                let files = self.data_files()?;
+                check_schemas(dataset, &files, self.strict_schema)?;
+
+                // Register all monthly files of every year as one table, mapped to the canonical columns
+                schema::register_merged(&ctx, dataset, &table, &files).await?;
+
+                info!("Loaded table '{}' from {} files", table, files.len());
+            }
+        }
+
+        codes::register_code_tables(&ctx)?;
+        window.register_calendar(&ctx)?;
+
+        // The zone lookup is optional unless explicitly requested
+        let zones_path = match &self.zones {
+            Some(path) => path.clone(),
+            None => self.data_root.join("taxi_zone_lookup.csv"),
+        };
+        let has_zones = self.zones.is_some() || zones_path.exists();
+        if has_zones {
+            register_zones(&ctx, &zones_path).await?;
+            info!("Loaded table 'zones' from: {}", zones_path.display());
+        } else {
+            warn!(
+                "⚠️  Taxi zone lookup not found at {}, borough/zone aggregations will be skipped.",
+                zones_path.display()
+            );
+        }
+
+        Ok(TlcSession {
+            ctx,
+            dataset,
+            table,
+            years: self.years,
+            window,
+            has_zones,
+        })
+    }
+}
+
+impl TlcSession {
+    /// The registered trip table, without the time window applied
+    pub async fn raw_trips(&self) -> Result<DataFrame> {
+        Ok(self.ctx.table(self.table.as_str()).await?)
+    }
+
+    /// The registered trip table with the time window applied
+    pub async fn trips(&self) -> Result<DataFrame> {
+        self.window.filter(self.raw_trips().await?)
+    }
+
+    /// Input of the [`crate::aggregations`], e.g.
+    /// `TripsByMonth.labeled_dataframe(&session.aggregation_input(false, PercentileMode::Exact).await?)`
+    pub async fn aggregation_input(
+        &self,
+        labels: bool,
+        percentiles: PercentileMode,
+    ) -> Result<AggregationInput> {
+        Ok(AggregationInput {
+            ctx: self.ctx.clone(),
+            table: self.table.clone(),
+            trips: self.trips().await?,
+            zones: match self.has_zones {
+                true => Some(self.ctx.table(ZONES_TABLE).await?),
+                false => None,
+            },
+            window: self.window.clone(),
+            labels,
+            percentiles,
+        })
+    }
+}
+
+/// Register the output of the `clean` command: accepted rows as `table` (already in the
+/// canonical schema) and, if present, rejected rows as `<table>_quarantine`
+pub async fn register_cleaned(ctx: &SessionContext, table: &str, dirs: &CleanDirs) -> Result<()> {
+    if !dirs.trips.is_dir() {
+        return Err(anyhow!(
+            "No cleaned dataset at {}, run the clean command first",
+            dirs.trips.display()
+        ));
+    }
+    ctx.register_parquet(
+        table,
+        dirs.trips.to_string_lossy().as_ref(),
+        ParquetReadOptions::default(),
+    )
+    .await?;
+    info!(
+        "Loaded table '{}' from cleaned dataset {}",
+        table,
+        dirs.trips.display()
+    );
+
+    if dirs.quarantine.is_dir() {
+        let quarantine = format!("{}_quarantine", table);
+        ctx.register_parquet(
+            &quarantine,
+            dirs.quarantine.to_string_lossy().as_ref(),
+            ParquetReadOptions::default(),
+        )
+        .await?;
+        info!(
+            "Loaded table '{}' from {}",
+            quarantine,
+            dirs.quarantine.display()
+        );
+    }
+    Ok(())
+}
+
+/// Compare every file's footer with the data dictionary of `dataset`: incompatible files
+/// are an error when `strict`, a warning otherwise
+fn check_schemas(dataset: Dataset, files: &[PathBuf], strict: bool) -> Result<()> {
+    let Some(expected) = expected_schema(dataset) else {
+        if strict {
+            return Err(anyhow!(
+                "No data dictionary for {} trips, --strict-schema only supports yellow",
+                dataset
+            ));
+        }
+        return Ok(());
+    };
+
+    let mut incompatible = Vec::new();
+    for path in files {
+        let drift = SchemaDrift::new(expected, &schema::read_arrow_schema(path)?);
+        if !drift.is_compatible() {
+            incompatible.push(format!(
+                "   - {}: {}",
+                path.display(),
+                drift.incompatible.join(", ")
+            ));
+        }
+    }
+    if incompatible.is_empty() {
+        return Ok(());
+    }
+
+    let message = format!(
+        "{} file(s) do not match the {} data dictionary:\n{}",
+        incompatible.len(),
+        dataset,
+        incompatible.join("\n")
+    );
+    if strict {
+        return Err(anyhow!(message));
+    }
+    warn!(
+        "⚠️  {}\nTip: run the validate command for details, --strict-schema refuses to run.\n",
+        message
+    );
+    Ok(())
+}
+
+/// Reject a trip table name that cannot be used unquoted in the SQL of the aggregations
+pub fn check_table_name(name: &str) -> Result<()> {
+    let mut chars = name.chars();
+    let is_identifier = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_lowercase() || c == '_')
+        && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    if !is_identifier {
+        return Err(anyhow!(
+            "Table name '{}' is not a lowercase SQL identifier ([a-z_][a-z0-9_]*)",
+            name
+        ));
+    }
+    Ok(())
+}
+
+/// Check that `data_dir` exists; warn about missing monthly files of `year`
+pub fn validate_data_dir(data_dir: &Path, pattern: &FilePattern, year: i32) -> Result<()> {
+    if !data_dir.exists() {
+        return Err(anyhow!(
+            "Data directory does not exist: {}",
+            data_dir.display()
+        ));
+    }
+    if !data_dir.is_dir() {
+        return Err(anyhow!(
+            "Data path is not a directory: {}",
+            data_dir.display()
+        ));
+    }
+
+    // Warning-only check: expect 12 files following the naming pattern
+    let mut missing = Vec::new();
+    for m in 1..=12 {
+        let fname = pattern.file_name(year, m);
+        if !data_dir.join(&fname).exists() {
+            missing.push(fname);
+        }
+    }
+
+    if !missing.is_empty() {
+        warn!(
+            "⚠️  Some expected files are missing in {}:\n{}\n\nTip: you can test with 1 month first and later add all 12 months.\n",
+            data_dir.display(),
+            missing
+                .iter()
+                .map(|f| format!("   - {}", f))
+                .collect::<Vec<_>>()
+                .join("\n")
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,165 @@
+use crate::output::OutputSink;
+use anyhow::{anyhow, Context, Result};
+use datafusion::common::{ParamValues, ScalarValue};
+use datafusion::prelude::*;
+use datafusion::sql::parser::DFParser;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Named query parameters (`$name` in SQL), kept as text until their type is known
+#[derive(Clone, Debug, Default)]
+pub struct QueryParams(HashMap<String, String>);
+
+impl QueryParams {
+    /// Read `name = value` lines; blank lines and `#` comments are ignored
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Cannot read params file {}", path.display()))?;
+
+        let mut params = HashMap::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, value) =
+                parse_param(line).map_err(|e| anyhow!("{}:{}: {}", path.display(), i + 1, e))?;
+            params.insert(name, value);
+        }
+        Ok(Self(params))
+    }
+
+    /// Add or override parameters (used for `--param` on top of `--params-file`)
+    pub fn extend(&mut self, params: impl IntoIterator<Item = (String, String)>) {
+        self.0.extend(params);
+    }
+
+    /// Typed values for the placeholders of `plan`.
+    ///
+    /// Each value is cast to the type DataFusion inferred for its placeholder
+    /// (e.g. `$start_date` compared with a timestamp column becomes a timestamp);
+    /// placeholders without an inferred type get an integer, float or string value.
+    fn bind(&self, plan: &datafusion::logical_expr::LogicalPlan) -> Result<ParamValues> {
+        let mut values = HashMap::new();
+        for (id, data_type) in plan.get_parameter_types()? {
+            let name = id.trim_start_matches('$');
+            let raw = self.0.get(name).ok_or_else(|| {
+                anyhow!("No value for parameter {}, pass --param {}=...", id, name)
+            })?;
+
+            let value = match data_type {
+                Some(data_type) => ScalarValue::from(raw.as_str())
+                    .cast_to(&data_type)
+                    .with_context(|| {
+                        format!("Parameter {}='{}' is not a valid {}", id, raw, data_type)
+                    })?,
+                None => literal(raw),
+            };
+            values.insert(name.to_string(), value);
+        }
+        Ok(values.into())
+    }
+}
+
+/// Best-effort literal for an untyped placeholder
+fn literal(raw: &str) -> ScalarValue {
+    if let Ok(i) = raw.parse::<i64>() {
+        ScalarValue::Int64(Some(i))
+    } else if let Ok(f) = raw.parse::<f64>() {
+        ScalarValue::Float64(Some(f))
+    } else {
+        ScalarValue::from(raw)
+    }
+}
+
+/// Parse `name=value` (used by `--param` and params files)
+pub fn parse_param(s: &str) -> Result<(String, String), String> {
+    let (name, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected name=value, got '{}'", s))?;
+    let name = name.trim().trim_start_matches('$');
+    if name.is_empty() {
+        return Err(format!("missing parameter name in '{}'", s));
+    }
+    Ok((name.to_string(), value.trim().to_string()))
+}
+
+/// Expand directories to the `.sql` files they contain (sorted), keep files as-is
+pub fn expand_sql_files(paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            let mut in_dir: Vec<PathBuf> = std::fs::read_dir(path)
+                .with_context(|| format!("Cannot read {}", path.display()))?
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| p.extension().is_some_and(|ext| ext == "sql"))
+                .collect();
+            in_dir.sort();
+            files.extend(in_dir);
+        } else {
+            files.push(path.clone());
+        }
+    }
+    Ok(files)
+}
+
+/// Execute every statement of a SQL file and emit each result under the file name.
+///
+/// A file with several statements produces `<stem>`, `<stem>_2`, ... outputs.
+pub async fn run_sql_file(
+    ctx: &SessionContext,
+    sink: &OutputSink,
+    path: &Path,
+    params: &QueryParams,
+) -> Result<()> {
+    let sql = std::fs::read_to_string(path)
+        .with_context(|| format!("Cannot read SQL file {}", path.display()))?;
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string());
+    let stem = path
+        .file_stem()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| file_name.clone());
+
+    run_sql(ctx, sink, &sql, &stem, &file_name, params)
+        .await
+        .with_context(|| format!("Cannot run {}", path.display()))
+}
+
+/// Execute every statement of `sql` with `params` bound and emit each result as `name`
+/// (`name_2`, ... when there are several statements)
+pub async fn run_sql(
+    ctx: &SessionContext,
+    sink: &OutputSink,
+    sql: &str,
+    name: &str,
+    title: &str,
+    params: &QueryParams,
+) -> Result<()> {
+    let statements = DFParser::parse_sql(sql).context("Cannot parse SQL")?;
+    let count = statements.len();
+    let state = ctx.state();
+
+    for (i, statement) in statements.into_iter().enumerate() {
+        let (name, title) = match count {
+            1 => (name.to_string(), title.to_string()),
+            _ => (
+                format!("{}_{}", name, i + 1),
+                format!("{} (statement {}/{})", title, i + 1, count),
+            ),
+        };
+
+        let plan = state
+            .statement_to_plan(statement)
+            .await
+            .with_context(|| format!("Cannot plan {}", title))?;
+        let values = params.bind(&plan)?;
+        let plan = plan.with_param_values(values)?;
+
+        let df = ctx.execute_logical_plan(plan).await?;
+        sink.emit(&name, &title, df).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,108 @@
+//! Checks of the `validate` command: folders, file names and Parquet footers, without
+//! scanning any data page.
+
+use crate::dictionary::{expected_schema, SchemaDrift};
+use crate::schema::read_arrow_schema;
+use crate::session::SessionBuilder;
+use anyhow::Result;
+use datafusion::arrow::array::{ArrayRef, Int64Array, RecordBatch, StringArray};
+use datafusion::arrow::datatypes::{DataType, Field, Schema};
+use log::warn;
+use std::sync::Arc;
+
+/// Outcome of [`validate`]
+pub struct ValidateSummary {
+    /// Files whose footer cannot be read
+    pub unreadable: usize,
+    /// Files that cannot be mapped to the trip table (see [`SchemaDrift::incompatible`])
+    pub incompatible: usize,
+    /// Readable, compatible files that still differ from the data dictionary
+    pub drifted: usize,
+}
+
+/// One row per monthly file: `file`, `columns` (from the footer), its drift from the data
+/// dictionary (`missing`, `extra` and `retyped` columns, NULL without a dictionary) and
+/// `status`: `ok`, `drift`, `incompatible: <reasons>` or the error reading the footer.
+///
+/// Missing folders are an error and missing months are printed as warnings, like when a
+/// session is built.
+pub fn validate(builder: &SessionBuilder) -> Result<(RecordBatch, ValidateSummary)> {
+    let files = builder.data_files()?;
+    let expected = expected_schema(builder.dataset());
+    if expected.is_none() {
+        warn!(
+            "⚠️  No data dictionary for {} trips, only footers are checked.",
+            builder.dataset()
+        );
+    }
+
+    let mut names = Vec::new();
+    let mut columns = Vec::new();
+    let mut missing = Vec::new();
+    let mut extra = Vec::new();
+    let mut retyped = Vec::new();
+    let mut statuses = Vec::new();
+    let mut summary = ValidateSummary {
+        unreadable: 0,
+        incompatible: 0,
+        drifted: 0,
+    };
+    for path in &files {
+        names.push(path.display().to_string());
+        let schema = match read_arrow_schema(path) {
+            Ok(schema) => schema,
+            Err(e) => {
+                columns.push(None);
+                missing.push(None);
+                extra.push(None);
+                retyped.push(None);
+                statuses.push(format!("{:#}", e));
+                summary.unreadable += 1;
+                continue;
+            }
+        };
+        columns.push(Some(schema.fields().len() as i64));
+
+        let Some(expected) = expected else {
+            missing.push(None);
+            extra.push(None);
+            retyped.push(None);
+            statuses.push("ok".to_string());
+            continue;
+        };
+        let drift = SchemaDrift::new(expected, &schema);
+        missing.push(Some(drift.missing.join(", ")));
+        extra.push(Some(drift.extra.join(", ")));
+        retyped.push(Some(drift.retyped.join(", ")));
+        statuses.push(if !drift.is_compatible() {
+            summary.incompatible += 1;
+            format!("incompatible: {}", drift.incompatible.join(", "))
+        } else if !drift.is_empty() {
+            summary.drifted += 1;
+            "drift".to_string()
+        } else {
+            "ok".to_string()
+        });
+    }
+
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("file", DataType::Utf8, false),
+        Field::new("columns", DataType::Int64, true),
+        Field::new("missing", DataType::Utf8, true),
+        Field::new("extra", DataType::Utf8, true),
+        Field::new("retyped", DataType::Utf8, true),
+        Field::new("status", DataType::Utf8, false),
+    ]));
+    let batch = RecordBatch::try_new(
+        schema,
+        vec![
+            Arc::new(StringArray::from(names)) as ArrayRef,
+            Arc::new(Int64Array::from(columns)),
+            Arc::new(StringArray::from(missing)),
+            Arc::new(StringArray::from(extra)),
+            Arc::new(StringArray::from(retyped)),
+            Arc::new(StringArray::from(statuses)),
+        ],
+    )?;
+    Ok((batch, summary))
+}
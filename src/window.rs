@@ -0,0 +1,388 @@
+use anyhow::{anyhow, Result};
+use chrono::{Days, NaiveDate, NaiveTime};
+use clap::ValueEnum;
+use datafusion::arrow::array::{RecordBatch, TimestampMicrosecondArray};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use datafusion::prelude::*;
+use std::fmt;
+use std::sync::Arc;
+
+/// One row per day of the window, used to gap-fill daily series
+pub const CALENDAR_TABLE: &str = "calendar_days";
+
+/// What to do with trips whose pickup falls outside the analyzed period
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum WindowMode {
+    /// Drop out-of-period trips before aggregating
+    Drop,
+    /// Keep out-of-period trips, but group them into a separate NULL pickup_month bucket
+    Isolate,
+    /// No time filtering (every row is aggregated as-is)
+    Off,
+}
+
+impl fmt::Display for WindowMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            WindowMode::Drop => "drop",
+            WindowMode::Isolate => "isolate",
+            WindowMode::Off => "off",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Half-open pickup time window `[start, end)` applied to the trip table.
+///
+/// Defaults to the analyzed calendar years; `--from`/`--to` narrow it.
+#[derive(Clone, Debug)]
+pub struct TimeWindow {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub mode: WindowMode,
+    column: String,
+}
+
+impl TimeWindow {
+    /// Build the window for `first_year..=last_year`, optionally narrowed by inclusive
+    /// `from`/`to` dates, which must fall within those years.
+    pub fn new(
+        column: &str,
+        first_year: i32,
+        last_year: i32,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+        mode: WindowMode,
+    ) -> Result<Self> {
+        let year_start = NaiveDate::from_ymd_opt(first_year, 1, 1)
+            .ok_or_else(|| anyhow!("Invalid year: {}", first_year))?;
+        let year_end = NaiveDate::from_ymd_opt(last_year + 1, 1, 1)
+            .ok_or_else(|| anyhow!("Invalid year: {}", last_year))?;
+
+        for (flag, date) in [("--from", from), ("--to", to)] {
+            if let Some(date) = date.filter(|d| *d < year_start || *d >= year_end) {
+                return Err(anyhow!(
+                    "{} {} is outside the analyzed years {}..={}",
+                    flag,
+                    date,
+                    first_year,
+                    last_year
+                ));
+            }
+        }
+
+        let start = from.unwrap_or(year_start);
+        // `--to` is inclusive on the command line, the window end is exclusive
+        let end = match to {
+            Some(to) => to
+                .checked_add_days(Days::new(1))
+                .ok_or_else(|| anyhow!("Invalid --to date: {}", to))?,
+            None => year_end,
+        };
+
+        if start >= end {
+            return Err(anyhow!(
+                "Empty time window [{}, {}): check --from/--to",
+                start,
+                end
+            ));
+        }
+
+        Ok(Self {
+            start,
+            end,
+            mode,
+            column: column.to_string(),
+        })
+    }
+
+    /// `start <= pickup < end` as a DataFrame expression
+    pub fn contains(&self) -> Expr {
+        let ts = |d: NaiveDate| {
+            cast(
+                lit(format!("{}T00:00:00", d)),
+                DataType::Timestamp(TimeUnit::Nanosecond, None),
+            )
+        };
+        col(&self.column)
+            .gt_eq(ts(self.start))
+            .and(col(&self.column).lt(ts(self.end)))
+    }
+
+    /// `start <= pickup < end` as a SQL predicate
+    pub fn contains_sql(&self) -> String {
+        format!(
+            "{col} >= TIMESTAMP '{} 00:00:00' AND {col} < TIMESTAMP '{} 00:00:00'",
+            self.start,
+            self.end,
+            col = self.column
+        )
+    }
+
+    /// Apply the window to a DataFrame (only filters in `drop` mode)
+    pub fn filter(&self, df: DataFrame) -> Result<DataFrame> {
+        match self.mode {
+            WindowMode::Drop => Ok(df.filter(self.contains())?),
+            WindowMode::Isolate | WindowMode::Off => Ok(df),
+        }
+    }
+
+    /// SQL `WHERE` clause equivalent of [`TimeWindow::filter`] (empty unless `drop`)
+    pub fn where_sql(&self) -> String {
+        match self.mode {
+            WindowMode::Drop => format!("WHERE {}", self.contains_sql()),
+            WindowMode::Isolate | WindowMode::Off => String::new(),
+        }
+    }
+
+    /// Wrap a time bucket so out-of-period rows map to NULL (only in `isolate` mode)
+    pub fn bucket(&self, expr: Expr) -> Result<Expr> {
+        match self.mode {
+            WindowMode::Isolate => Ok(when(self.contains(), expr).end()?),
+            WindowMode::Drop | WindowMode::Off => Ok(expr),
+        }
+    }
+
+    /// SQL equivalent of [`TimeWindow::bucket`]
+    pub fn bucket_sql(&self, expr: &str) -> String {
+        match self.mode {
+            WindowMode::Isolate => format!("CASE WHEN {} THEN {} END", self.contains_sql(), expr),
+            WindowMode::Drop | WindowMode::Off => expr.to_string(),
+        }
+    }
+
+    /// Count rows whose pickup is outside the window (NULL pickups included)
+    pub async fn count_outside(&self, df: DataFrame) -> Result<usize> {
+        Ok(df.filter(self.contains().is_not_true())?.count().await?)
+    }
+
+    /// `calendar_days(day)`: every day of `[start, end)` as a midnight timestamp
+    pub fn calendar(&self) -> Result<RecordBatch> {
+        let days: Vec<i64> = self
+            .start
+            .iter_days()
+            .take_while(|d| *d < self.end)
+            .map(|d| d.and_time(NaiveTime::MIN).and_utc().timestamp_micros())
+            .collect();
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "day",
+            DataType::Timestamp(TimeUnit::Microsecond, None),
+            false,
+        )]));
+        Ok(RecordBatch::try_new(
+            schema,
+            vec![Arc::new(TimestampMicrosecondArray::from(days))],
+        )?)
+    }
+
+    /// Register [`TimeWindow::calendar`] as [`CALENDAR_TABLE`] in `ctx`
+    pub fn register_calendar(&self, ctx: &SessionContext) -> Result<()> {
+        let df = ctx.read_batch(self.calendar()?)?;
+        ctx.register_table(CALENDAR_TABLE, df.into_view())?;
+        Ok(())
+    }
+
+    /// One-line summary of how out-of-period rows were handled
+    pub fn report(&self, outside: usize) -> String {
+        match self.mode {
+            WindowMode::Drop => format!("Excluded {} trips with pickup outside {}", outside, self),
+            WindowMode::Isolate => format!(
+                "Isolated {} trips with pickup outside {} into the NULL pickup_month bucket",
+                outside, self
+            ),
+            WindowMode::Off => format!(
+                "{} trips have a pickup outside {} (window mode is off, nothing excluded)",
+                outside, self
+            ),
+        }
+    }
+}
+
+impl fmt::Display for TimeWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}, {})", self.start, self.end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::{AsArray, StringArray};
+    use datafusion::arrow::datatypes::TimestampMicrosecondType;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn window(from: Option<&str>, to: Option<&str>, mode: WindowMode) -> Result<TimeWindow> {
+        TimeWindow::new(
+            "pickup_datetime",
+            2024,
+            2025,
+            from.map(date),
+            to.map(date),
+            mode,
+        )
+    }
+
+    #[test]
+    fn defaults_to_the_analyzed_years() {
+        let w = window(None, None, WindowMode::Drop).unwrap();
+        assert_eq!((w.start, w.end), (date("2024-01-01"), date("2026-01-01")));
+    }
+
+    #[test]
+    fn to_is_inclusive() {
+        let w = window(Some("2024-03-01"), Some("2024-03-31"), WindowMode::Drop).unwrap();
+        assert_eq!((w.start, w.end), (date("2024-03-01"), date("2024-04-01")));
+        assert_eq!(w.to_string(), "[2024-03-01, 2024-04-01)");
+
+        let single_day = window(Some("2025-12-31"), Some("2025-12-31"), WindowMode::Drop);
+        assert_eq!(single_day.unwrap().end, date("2026-01-01"));
+    }
+
+    #[test]
+    fn rejects_inverted_windows_and_dates_outside_the_years() {
+        assert!(window(Some("2024-03-02"), Some("2024-03-01"), WindowMode::Drop).is_err());
+        assert!(window(Some("2023-12-31"), None, WindowMode::Drop).is_err());
+        assert!(window(Some("2026-01-01"), None, WindowMode::Drop).is_err());
+        assert!(window(None, Some("2026-01-01"), WindowMode::Drop).is_err());
+        assert!(window(None, Some("2023-06-30"), WindowMode::Drop).is_err());
+    }
+
+    #[test]
+    fn where_and_bucket_sql_per_mode() {
+        let range = "pickup_datetime >= TIMESTAMP '2024-03-01 00:00:00' \
+                     AND pickup_datetime < TIMESTAMP '2024-04-01 00:00:00'";
+        let sql = |mode| {
+            let w = window(Some("2024-03-01"), Some("2024-03-31"), mode).unwrap();
+            (w.where_sql(), w.bucket_sql("x"))
+        };
+        assert_eq!(
+            sql(WindowMode::Drop),
+            (format!("WHERE {}", range), "x".into())
+        );
+        assert_eq!(
+            sql(WindowMode::Isolate),
+            (String::new(), format!("CASE WHEN {} THEN x END", range))
+        );
+        assert_eq!(sql(WindowMode::Off), (String::new(), "x".into()));
+    }
+
+    /// Pickups at and around both edges of `[2024-03-01, 2024-04-01)`, plus NULL
+    fn edge_trips(ctx: &SessionContext) -> Result<DataFrame> {
+        let micros = |s: &str| {
+            chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f")
+                .unwrap()
+                .and_utc()
+                .timestamp_micros()
+        };
+        let pickups = [
+            "2024-02-29 23:59:59.999999",
+            "2024-03-01 00:00:00.0",
+            "2024-03-31 23:59:59.999999",
+            "2024-04-01 00:00:00.0",
+        ];
+        let mut values: Vec<Option<i64>> = pickups.iter().map(|p| Some(micros(p))).collect();
+        values.push(None);
+        let ids: Vec<String> = (0..values.len()).map(|i| i.to_string()).collect();
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Utf8, false),
+            Field::new(
+                "pickup_datetime",
+                DataType::Timestamp(TimeUnit::Microsecond, None),
+                true,
+            ),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(StringArray::from(ids)),
+                Arc::new(TimestampMicrosecondArray::from(values)),
+            ],
+        )?;
+        ctx.register_batch("trips", batch.clone())?;
+        Ok(ctx.read_batch(batch)?)
+    }
+
+    async fn ids(df: DataFrame) -> Vec<String> {
+        let batches = df
+            .sort(vec![col("id").sort(true, true)])
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        batches
+            .iter()
+            .flat_map(|b| {
+                b.column(0)
+                    .as_string::<i32>()
+                    .iter()
+                    .map(|v| v.unwrap().to_string())
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn dataframe_and_sql_agree_at_the_edges() {
+        let ctx = SessionContext::new();
+        let trips = edge_trips(&ctx).unwrap();
+
+        for mode in [WindowMode::Drop, WindowMode::Isolate, WindowMode::Off] {
+            let w = window(Some("2024-03-01"), Some("2024-03-31"), mode).unwrap();
+            let kept = ids(w.filter(trips.clone()).unwrap()).await;
+            let kept_sql = ids(ctx
+                .sql(&format!("SELECT id FROM trips {}", w.where_sql()))
+                .await
+                .unwrap())
+            .await;
+            assert_eq!(kept, kept_sql, "{}", mode);
+
+            let bucket = trips
+                .clone()
+                .select(vec![
+                    col("id"),
+                    w.bucket(col("pickup_datetime")).unwrap().alias("b"),
+                ])
+                .unwrap()
+                .filter(col("b").is_not_null())
+                .unwrap();
+            let bucket_sql = ctx
+                .sql(&format!(
+                    "SELECT id FROM (SELECT id, {} AS b FROM trips) WHERE b IS NOT NULL",
+                    w.bucket_sql("pickup_datetime")
+                ))
+                .await
+                .unwrap();
+            assert_eq!(ids(bucket).await, ids(bucket_sql).await, "{}", mode);
+        }
+
+        let w = window(Some("2024-03-01"), Some("2024-03-31"), WindowMode::Drop).unwrap();
+        let inside = ids(trips.clone().filter(w.contains()).unwrap()).await;
+        let inside_sql = ids(ctx
+            .sql(&format!("SELECT id FROM trips WHERE {}", w.contains_sql()))
+            .await
+            .unwrap())
+        .await;
+        assert_eq!(inside, ["1", "2"]);
+        assert_eq!(inside, inside_sql);
+        assert_eq!(w.count_outside(trips).await.unwrap(), 3);
+    }
+
+    #[test]
+    fn calendar_has_one_row_per_day() {
+        let w = window(Some("2024-02-01"), Some("2024-02-29"), WindowMode::Drop).unwrap();
+        let calendar = w.calendar().unwrap();
+        assert_eq!(calendar.num_rows(), 29);
+        let days = calendar
+            .column(0)
+            .as_primitive::<TimestampMicrosecondType>();
+        assert_eq!(
+            days.value(0),
+            date("2024-02-01")
+                .and_time(NaiveTime::MIN)
+                .and_utc()
+                .timestamp_micros()
+        );
+    }
+}
@@ -0,0 +1,47 @@
+use anyhow::{Context, Result};
+use datafusion::arrow::datatypes::DataType;
+use datafusion::prelude::*;
+use std::path::Path;
+
+/// Name of the registered taxi zone lookup table
+pub const ZONES_TABLE: &str = "zones";
+
+/// Register the TLC `taxi_zone_lookup.csv` as the `zones` table with columns
+/// `location_id`, `borough`, `zone` and `service_zone`.
+pub async fn register_zones(ctx: &SessionContext, path: &Path) -> Result<()> {
+    let raw = ctx
+        .read_csv(
+            path.to_string_lossy().as_ref(),
+            CsvReadOptions::new().has_header(true),
+        )
+        .await
+        .with_context(|| format!("Cannot read taxi zone lookup {}", path.display()))?;
+
+    let zones = raw
+        .select(vec![
+            cast(ident("LocationID"), DataType::Int64).alias("location_id"),
+            ident("Borough").alias("borough"),
+            ident("Zone").alias("zone"),
+            ident("service_zone").alias("service_zone"),
+        ])
+        .with_context(|| {
+            format!(
+                "{} is missing LocationID/Borough/Zone/service_zone columns",
+                path.display()
+            )
+        })?;
+
+    ctx.register_table(ZONES_TABLE, zones.into_view())?;
+    Ok(())
+}
+
+/// The `zones` table with its columns renamed under `prefix`
+/// (e.g. `pickup_` -> `pickup_location_id`, `pickup_borough`, `pickup_zone`), so it
+/// can be joined twice against the same trip table.
+pub fn zones_as(zones: DataFrame, prefix: &str) -> Result<DataFrame> {
+    Ok(zones.select(vec![
+        col("location_id").alias(format!("{}location_id", prefix)),
+        col("borough").alias(format!("{}borough", prefix)),
+        col("zone").alias(format!("{}zone", prefix)),
+    ])?)
+}